@@ -0,0 +1,51 @@
+//! Compares logging with the cached name of the thread against replacing the cached name before
+//! every record, which looks the name up and converts it as would be done without the cache.
+//!
+//! Run with `cargo bench --bench thread_info`.
+
+use std::ffi::c_void;
+use std::hint::black_box;
+use std::ptr::NonNull;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ffi_logger::{FfiCallback, FfiLogRecord, FfiLogger};
+
+const ITERATIONS: u32 = 200_000;
+
+extern "C" fn sink(_: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+    black_box(unsafe { (*record).thread_name });
+    0
+}
+
+fn time(f: impl Fn()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let logger = unsafe { FfiLogger::with_callback(FfiCallback::Record(sink), None) };
+    log::set_boxed_logger(Box::new(logger)).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    thread::Builder::new()
+        .name("bench".into())
+        .spawn(|| {
+            let cached = time(|| log::info!("message"));
+            // Going back to the name the standard library knows replaces the cached name with
+            // a newly converted one, so each record is passed a name that was just looked up.
+            let uncached = time(|| {
+                FfiLogger::set_thread_name(None);
+                log::info!("message");
+            });
+
+            println!("cached thread name:      {cached:?} per record");
+            println!("looked up for each one:  {uncached:?} per record");
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
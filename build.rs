@@ -0,0 +1,102 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "cbindgen")]
+    header::generate();
+}
+
+/// Writing the C header for the `cbindgen` feature.
+#[cfg(feature = "cbindgen")]
+mod header {
+    use std::env;
+    use std::path::PathBuf;
+
+    /// The environment variable with the path to write the header to, instead of `OUT_DIR`.
+    const PATH_VAR: &str = "FFI_LOGGER_HEADER";
+
+    /// The features that some exported items depend on, and the macro guarding those items.
+    const FEATURES: [(&str, &str); 2] = [("std", "FFI_LOGGER_STD"), ("kv", "FFI_LOGGER_KV")];
+
+    /// The types of the callbacks, which are only passed to the logger from Rust, but are
+    /// implemented in C.
+    const CALLBACKS: [&str; 14] = [
+        "Callback",
+        "RecordCallback",
+        "BufferCallback",
+        "RawCallback",
+        "SyslogCallback",
+        "ContextCallback",
+        "IdCallback",
+        "OwnedCallback",
+        "BatchCallback",
+        "FormatCallback",
+        "FlushCallback",
+        "FilterCallback",
+        "ErrorCallback",
+        "KvCallback",
+    ];
+
+    pub(super) fn generate() {
+        println!("cargo:rerun-if-changed=src");
+        println!("cargo:rerun-if-env-changed={PATH_VAR}");
+
+        let path = match env::var_os(PATH_VAR) {
+            Some(path) => PathBuf::from(path),
+            None => PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("ffi_logger.h"),
+        };
+        let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+
+        // Items that are only exported with some features are guarded by a macro for each of
+        // them, which the header defines for the features it is generated with.
+        let enabled: String = FEATURES
+            .iter()
+            .filter(|(feature, _)| {
+                env::var_os(format!("CARGO_FEATURE_{}", feature.to_uppercase())).is_some()
+            })
+            .map(|(_, define)| format!("\n#define {define}"))
+            .collect();
+
+        let config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            include_guard: Some("FFI_LOGGER_H".into()),
+            after_includes: Some(enabled),
+            defines: FEATURES
+                .map(|(feature, define)| (format!("feature = {feature}"), define.into()))
+                .into(),
+            cpp_compat: true,
+            style: cbindgen::Style::Type,
+            usize_is_size_t: true,
+            enumeration: cbindgen::EnumConfig {
+                rename_variants: cbindgen::RenameRule::QualifiedScreamingSnakeCase,
+                ..Default::default()
+            },
+            export: cbindgen::ExportConfig {
+                include: ["FfiLevel", "FfiLogRecord"]
+                    .iter()
+                    .chain(&CALLBACKS)
+                    .map(|name| name.to_string())
+                    .collect(),
+                exclude: ["os_log_create", "Option_Callback", "Option_KvCallback"]
+                    .map(String::from)
+                    .to_vec(),
+                // The callback types are prefixed so that they do not clash with other names in
+                // C, and the function pointer types are nullable in C already.
+                rename: CALLBACKS
+                    .iter()
+                    .map(|callback| (callback.to_string(), format!("FfiLogger{callback}")))
+                    .chain([
+                        ("Option_Callback".into(), "FfiLoggerCallback".into()),
+                        ("Option_KvCallback".into(), "FfiLoggerKvCallback".into()),
+                    ])
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        cbindgen::Builder::new()
+            .with_crate(crate_dir)
+            .with_config(config)
+            .generate()
+            .expect("the exported functions and types could not be turned into a header")
+            .write_to_file(&path);
+    }
+}
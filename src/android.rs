@@ -0,0 +1,77 @@
+use core::ffi::{c_char, c_int, c_void};
+use core::ptr::NonNull;
+
+use crate::{FfiCallback, FfiLevel, FfiLogRecord, FfiLogger, FfiLoggerBuilder};
+
+/// The shape of `__android_log_write` from `<android/log.h>`, taking the priority, the tag and
+/// the null-terminated message.
+pub type AndroidLogWrite = unsafe extern "C" fn(c_int, *const c_char, *const c_char) -> c_int;
+
+impl FfiLevel {
+    /// The `ANDROID_LOG_*` priority of the level, as passed to [AndroidLogWrite].
+    ///
+    /// | `FfiLevel` | Priority                 |
+    /// |------------|--------------------------|
+    /// | `Error`    | 6 (`ANDROID_LOG_ERROR`)  |
+    /// | `Warn`     | 5 (`ANDROID_LOG_WARN`)   |
+    /// | `Info`     | 4 (`ANDROID_LOG_INFO`)   |
+    /// | `Debug`    | 3 (`ANDROID_LOG_DEBUG`)  |
+    /// | `Trace`    | 2 (`ANDROID_LOG_VERBOSE`)|
+    pub const fn android_priority(self) -> c_int {
+        match self {
+            FfiLevel::Error => 6,
+            FfiLevel::Warn => 5,
+            FfiLevel::Info => 4,
+            FfiLevel::Debug => 3,
+            FfiLevel::Trace => 2,
+        }
+    }
+}
+
+impl FfiLogger {
+    /// Start building a logger that writes to logcat through `write`, which is usually
+    /// `__android_log_write`. The target of each record is its tag, and its level is mapped
+    /// with [FfiLevel::android_priority].
+    ///
+    /// The function is passed as the user data, so [FfiLoggerBuilder::data] must not be
+    /// changed.
+    ///
+    /// ```no_run
+    /// use std::ffi::{c_char, c_int};
+    ///
+    /// use ffi_logger::FfiLogger;
+    ///
+    /// extern "C" {
+    ///     fn __android_log_write(prio: c_int, tag: *const c_char, text: *const c_char) -> c_int;
+    /// }
+    ///
+    /// // Safety: `__android_log_write` can be called from any thread.
+    /// let logger = unsafe { FfiLogger::android(__android_log_write).build() };
+    /// logger.leak_and_register().unwrap();
+    /// ```
+    pub fn android(write: AndroidLogWrite) -> FfiLoggerBuilder {
+        let data = NonNull::new(write as *mut c_void);
+        Self::builder(FfiCallback::Record(trampoline)).data(data)
+    }
+}
+
+/// Passes the record to the `__android_log_write`-style function given as the user data.
+extern "C" fn trampoline(data: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+    let (Some(data), Some(record)) = (data, unsafe { record.as_ref() }) else {
+        return 0;
+    };
+    // Safety: The data is only ever set to an `AndroidLogWrite` by `FfiLogger::android`.
+    let write: AndroidLogWrite = unsafe { core::mem::transmute(data.as_ptr()) };
+
+    // Safety: The strings of the record are valid for the duration of the call.
+    match unsafe {
+        write(
+            record.level.android_priority(),
+            record.target,
+            record.message,
+        )
+    } {
+        ..0 => -1,
+        _ => record.message_len as isize,
+    }
+}
@@ -0,0 +1,52 @@
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::clock;
+
+/// Stops delivering to a sink for a while after it fails too many times in a row.
+#[derive(Debug)]
+pub(crate) struct Backpressure {
+    threshold: u32,
+    cooldown: Duration,
+    failures: AtomicU32,
+    /// The [clock::monotonic_nanos] at which to resume delivering, or zero if not backing off.
+    resume_at: AtomicU64,
+}
+
+impl Backpressure {
+    pub(crate) fn new(threshold: u32, cooldown: Duration) -> Backpressure {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            failures: AtomicU32::new(0),
+            resume_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether delivering should currently be skipped.
+    pub(crate) fn is_backing_off(&self) -> bool {
+        match self.resume_at.load(Ordering::Relaxed) {
+            0 => false,
+            resume_at => clock::monotonic_nanos().is_some_and(|now| now < resume_at),
+        }
+    }
+
+    /// Record whether delivering a message failed, starting to back off if it failed too many
+    /// times in a row.
+    pub(crate) fn record(&self, failed: bool) {
+        if !failed {
+            self.failures.store(0, Ordering::Relaxed);
+            return;
+        }
+
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.threshold {
+            self.failures.store(0, Ordering::Relaxed);
+            if let Some(now) = clock::monotonic_nanos() {
+                let cooldown = self.cooldown.as_nanos() as u64;
+                self.resume_at
+                    .store(now.saturating_add(cooldown).max(1), Ordering::Relaxed);
+            }
+        }
+    }
+}
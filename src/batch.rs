@@ -0,0 +1,98 @@
+use alloc::ffi::CString;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::mem;
+use core::ptr;
+use core::time::Duration;
+
+use crate::clock;
+use crate::dispatch::{Header, RecordStrings};
+use crate::record::FfiLogRecord;
+use crate::sync::Mutex;
+
+/// A record waiting to be delivered to the batch callbacks.
+#[derive(Debug)]
+struct Pending {
+    header: Header,
+    /// The name of the thread that logged the record, as it may exit before the record is
+    /// delivered.
+    thread_name: Option<CString>,
+    strings: RecordStrings,
+    message: CString,
+}
+
+// Safety: The thread name in the header is never used, as the record owns a copy of it.
+unsafe impl Send for Pending {}
+
+/// The records collected for [FfiCallback::Batch](crate::FfiCallback::Batch), and when they
+/// are delivered.
+#[derive(Debug)]
+pub(crate) struct Batch {
+    /// The pending records, and the [clock::monotonic_nanos] at which the oldest one was
+    /// added.
+    pending: Mutex<(Vec<Pending>, Option<u64>)>,
+    max_records: usize,
+    max_delay: Option<Duration>,
+}
+
+impl Batch {
+    /// Collect up to `max_records` records, or as many as were logged within `max_delay` of the
+    /// oldest one.
+    pub(crate) fn new(max_records: usize, max_delay: Option<Duration>) -> Batch {
+        Self {
+            pending: Mutex::new((Vec::new(), None)),
+            max_records: max_records.max(1),
+            max_delay,
+        }
+    }
+
+    /// Add a record, returning whether the batch should be delivered now.
+    pub(crate) fn push(&self, header: &Header, strings: RecordStrings, message: &CStr) -> bool {
+        let now = clock::monotonic_nanos();
+
+        let mut guard = self.pending.lock();
+        let (pending, oldest) = &mut *guard;
+        if pending.is_empty() {
+            *oldest = now;
+        }
+        pending.push(Pending {
+            header: *header,
+            thread_name: (!header.thread_name.is_null())
+                // Safety: The name of the thread is valid for the duration of the call.
+                .then(|| unsafe { CStr::from_ptr(header.thread_name) }.into()),
+            strings,
+            message: message.into(),
+        });
+
+        let expired = match (self.max_delay, *oldest, now) {
+            (Some(delay), Some(oldest), Some(now)) => now - oldest >= delay.as_nanos() as u64,
+            _ => false,
+        };
+        pending.len() >= self.max_records || expired
+    }
+
+    /// Take the pending records and call the function with them, if there are any.
+    ///
+    /// The lock is not held while calling the function, so that a callback can log or flush.
+    pub(crate) fn take(&self, f: impl FnOnce(&[FfiLogRecord])) {
+        let (pending, _) = mem::take(&mut *self.pending.lock());
+        if pending.is_empty() {
+            return;
+        }
+
+        let records: Vec<FfiLogRecord> = pending
+            .iter()
+            .map(|pending| {
+                let header = Header {
+                    thread_name: pending
+                        .thread_name
+                        .as_deref()
+                        .map_or(ptr::null(), CStr::as_ptr),
+                    ..pending.header
+                };
+                pending.strings.record(&header, &pending.message)
+            })
+            .collect();
+        f(&records);
+    }
+}
@@ -0,0 +1,340 @@
+use alloc::ffi::CString;
+#[cfg(windows)]
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::fmt::{self, Write as _};
+use core::num::NonZeroU8;
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::thread::LocalKey;
+
+#[cfg(feature = "std")]
+thread_local! {
+    static BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    /// Holds the message of a record while it is compared with the previous one, as [BUFFER]
+    /// is needed again to deliver it.
+    static ARGS: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Format a message into a buffer reused by the current thread, and call the function with it.
+///
+/// If the buffer of the thread is unavailable, such as when a callback logs while handling
+/// another message, a new buffer is allocated instead. Without the standard library there are
+/// no thread-locals, so a new buffer is always allocated.
+pub(crate) fn with_message<R>(
+    format: impl FnOnce(&mut Vec<u8>),
+    f: impl FnOnce(&mut Message<'_>) -> R,
+) -> R {
+    let format_and_call = |buffer: &mut Vec<u8>| {
+        buffer.clear();
+        format(buffer);
+        buffer.push(0);
+
+        f(&mut Message::new(buffer))
+    };
+
+    #[cfg(feature = "std")]
+    return with_buffer(&BUFFER, format_and_call);
+    #[cfg(not(feature = "std"))]
+    format_and_call(&mut Vec::new())
+}
+
+/// Format only the arguments of a message, cut off at the limit, into a second buffer reused by
+/// the current thread, and call the function with them. The buffer is handled the same way as
+/// by [with_message], which can be called from the function.
+pub(crate) fn with_args<R>(args: fmt::Arguments, limit: usize, f: impl FnOnce(&str) -> R) -> R {
+    let format_and_call = |buffer: &mut Vec<u8>| {
+        buffer.clear();
+        write_limited(buffer, 0, args, Some(limit));
+
+        // Safety: Only whole strings are written, and they are only cut off at character
+        // boundaries.
+        f(unsafe { core::str::from_utf8_unchecked(buffer) })
+    };
+
+    #[cfg(feature = "std")]
+    return with_buffer(&ARGS, format_and_call);
+    #[cfg(not(feature = "std"))]
+    format_and_call(&mut Vec::new())
+}
+
+/// Call the function with the buffer of the current thread, or with a new one if that is
+/// unavailable.
+#[cfg(feature = "std")]
+fn with_buffer<R>(
+    key: &'static LocalKey<RefCell<Vec<u8>>>,
+    f: impl FnOnce(&mut Vec<u8>) -> R,
+) -> R {
+    let mut f = Some(f);
+    if let Ok(Some(result)) = key.try_with(|buffer| {
+        let mut buffer = buffer.try_borrow_mut().ok()?;
+        Some((f.take().unwrap())(&mut buffer))
+    }) {
+        return result;
+    }
+
+    (f.take().unwrap())(&mut Vec::new())
+}
+
+/// Reserve room for at least `size` bytes in the buffer of the current thread.
+#[cfg(feature = "std")]
+pub(crate) fn reserve(size: usize) {
+    let _ = BUFFER.try_with(|buffer| {
+        if let Ok(mut buffer) = buffer.try_borrow_mut() {
+            let additional = size.saturating_sub(buffer.len());
+            buffer.reserve(additional);
+        }
+    });
+}
+
+/// What a message that is cut off ends with.
+const ELLIPSIS: &str = "...";
+
+/// Format into the end of the buffer. If a limit is given and everything in the buffer after
+/// `start` would be longer than it, the message is cut off at a character boundary and ends with
+/// an ellipsis instead.
+///
+/// Returns whether the message was cut off. A formatting error leaves the message as it was
+/// written until then.
+pub(crate) fn write_limited(
+    buffer: &mut Vec<u8>,
+    start: usize,
+    args: fmt::Arguments,
+    limit: Option<usize>,
+) -> bool {
+    let Some(limit) = limit else {
+        let _ = Writer(buffer).write_fmt(args);
+        return false;
+    };
+
+    let mut limited = Limited {
+        buffer,
+        start,
+        limit,
+        truncated: false,
+    };
+    let _ = limited.write_fmt(args);
+    limited.truncated
+}
+
+/// Formats into the end of a buffer, until more than `limit` bytes follow `start`.
+struct Limited<'a> {
+    buffer: &'a mut Vec<u8>,
+    start: usize,
+    limit: usize,
+    truncated: bool,
+}
+
+impl fmt::Write for Limited<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Err(fmt::Error);
+        }
+
+        let len = self.buffer.len() - self.start;
+        if len + s.len() <= self.limit {
+            self.buffer.extend_from_slice(s.as_bytes());
+            return Ok(());
+        }
+
+        let ellipsis = &ELLIPSIS[..ELLIPSIS.len().min(self.limit)];
+        let end = self.limit - ellipsis.len();
+        if end >= len {
+            let end = (0..=end - len)
+                .rev()
+                .find(|&end| s.is_char_boundary(end))
+                .unwrap_or(0);
+            self.buffer.extend_from_slice(&s.as_bytes()[..end]);
+        } else {
+            // Continuation bytes of UTF-8 sequences are all `0b10xxxxxx`.
+            let end = (self.start..=self.start + end)
+                .rev()
+                .find(|&end| self.buffer.get(end).is_none_or(|byte| byte & 0xC0 != 0x80))
+                .unwrap_or(self.start);
+            self.buffer.truncate(end);
+        }
+        self.buffer.extend_from_slice(ellipsis.as_bytes());
+
+        // Stop formatting, as nothing more will be written.
+        self.truncated = true;
+        Err(fmt::Error)
+    }
+}
+
+/// Handle the interior null bytes of the message after `start` in place, as
+/// [Message::c_str] would.
+pub(crate) fn sanitize(buffer: &mut Vec<u8>, start: usize, interior_nul: InteriorNul) {
+    match interior_nul {
+        InteriorNul::Replace(replacement) => {
+            for byte in buffer[start..].iter_mut().filter(|byte| **byte == 0) {
+                *byte = replacement.get();
+            }
+        }
+        InteriorNul::Truncate => {
+            if let Some(end) = buffer[start..].iter().position(|&byte| byte == 0) {
+                buffer.truncate(start + end);
+            }
+        }
+    }
+}
+
+/// Formats into the end of a buffer.
+pub(crate) struct Writer<'a>(pub(crate) &'a mut Vec<u8>);
+
+impl fmt::Write for Writer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// How interior null bytes in a message are handled when it is passed as a C string.
+///
+/// This only applies to the message, and not to callbacks receiving its exact bytes such as
+/// [RawCallback](crate::RawCallback). On Windows, null code units in messages passed as UTF-16
+/// are handled the same way.
+///
+/// Either way messages always become valid C strings, so logging never fails or panics because
+/// of them, even when the message is nothing but null bytes:
+///
+/// ```
+/// use std::ffi::{c_char, c_void, CStr};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+///     let message = unsafe { CStr::from_ptr(message) };
+///     assert_eq!(message.to_bytes(), [0x1A; 64]);
+///     message.to_bytes().len() as isize
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Message(sink), None) };
+/// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+/// log::set_logger(logger).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!("{}", "\0".repeat(64));
+/// let handle = LogHandle::new(logger);
+/// assert_eq!(handle.dropped_count(), 0);
+/// assert_eq!(handle.short_writes(), 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InteriorNul {
+    /// Replace each interior null byte with this byte.
+    Replace(NonZeroU8),
+    /// Cut the message off at the first interior null byte.
+    Truncate,
+}
+
+impl Default for InteriorNul {
+    /// Replace interior null bytes with the ASCII substitute character (`0x1A`).
+    fn default() -> InteriorNul {
+        InteriorNul::Replace(NonZeroU8::new(0x1A).unwrap())
+    }
+}
+
+/// A formatted message, which can be delivered either as its exact bytes or as a C string.
+pub(crate) struct Message<'a> {
+    /// The formatted bytes, followed by a null terminator unless they were borrowed.
+    bytes: &'a [u8],
+    terminated: bool,
+    /// The message with interior null bytes replaced, if there were any, or with a null
+    /// terminator if it had none.
+    sanitized: Option<CString>,
+    /// The message as null-terminated UTF-16, once it was needed.
+    #[cfg(windows)]
+    wide: Option<Vec<u16>>,
+}
+
+impl<'a> Message<'a> {
+    /// A message from the bytes, which must end with a null terminator.
+    pub(crate) fn new(bytes: &'a [u8]) -> Message<'a> {
+        Message {
+            bytes,
+            terminated: true,
+            sanitized: None,
+            #[cfg(windows)]
+            wide: None,
+        }
+    }
+
+    /// A message of exactly the bytes, without a null terminator. They are only copied if the
+    /// message is needed as a C string.
+    pub(crate) fn borrowed(bytes: &'a [u8]) -> Message<'a> {
+        Message {
+            terminated: false,
+            ..Message::new(bytes)
+        }
+    }
+
+    /// The exact bytes that were formatted, not including the null terminator.
+    pub(crate) fn raw(&self) -> &'a [u8] {
+        match self.terminated {
+            true => &self.bytes[..self.bytes.len() - 1],
+            false => self.bytes,
+        }
+    }
+
+    /// The message as a C string, with any interior null bytes handled as given.
+    ///
+    /// This only allocates if there are interior null bytes to replace, or if the message was
+    /// borrowed.
+    pub(crate) fn c_str(&mut self, interior_nul: InteriorNul) -> &CStr {
+        if self.terminated {
+            if let Ok(c_str) = CStr::from_bytes_with_nul(self.bytes) {
+                return c_str;
+            }
+        }
+
+        let bytes = self.raw();
+        self.sanitized.get_or_insert_with(|| match interior_nul {
+            InteriorNul::Replace(replacement) => {
+                let mut bytes = bytes.to_vec();
+                for byte in bytes.iter_mut().filter(|byte| **byte == 0) {
+                    *byte = replacement.get();
+                }
+
+                // Safety: All null bytes were replaced above.
+                unsafe { CString::from_vec_unchecked(bytes) }
+            }
+            InteriorNul::Truncate => {
+                let end = bytes.iter().position(|&byte| byte == 0);
+                let bytes = bytes[..end.unwrap_or(bytes.len())].to_vec();
+
+                // Safety: The bytes end before the first null byte.
+                unsafe { CString::from_vec_unchecked(bytes) }
+            }
+        })
+    }
+
+    /// The message as null-terminated UTF-16, with any interior null code units handled as
+    /// given.
+    ///
+    /// This is only converted once, no matter how many callbacks need it.
+    #[cfg(windows)]
+    pub(crate) fn wide(&mut self, interior_nul: InteriorNul) -> &[u16] {
+        let bytes = self.raw();
+        self.wide.get_or_insert_with(|| {
+            let message = String::from_utf8_lossy(bytes);
+            let mut wide: Vec<u16> = message.encode_utf16().collect();
+
+            match interior_nul {
+                InteriorNul::Replace(replacement) => {
+                    for unit in wide.iter_mut().filter(|unit| **unit == 0) {
+                        *unit = replacement.get().into();
+                    }
+                }
+                InteriorNul::Truncate => {
+                    if let Some(end) = wide.iter().position(|&unit| unit == 0) {
+                        wide.truncate(end);
+                    }
+                }
+            }
+            wide.push(0);
+            wide
+        })
+    }
+}
@@ -0,0 +1,1587 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ffi::{c_void, CStr};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8};
+use core::time::Duration;
+
+use log::{Level, LevelFilter};
+
+use crate::backpressure::Backpressure;
+use crate::batch::Batch;
+#[cfg(feature = "std")]
+use crate::buffer;
+use crate::dedup::Dedup;
+use crate::dispatch::Dispatcher;
+use crate::filter::{TargetLevels, TargetLists};
+use crate::format::Formatter;
+use crate::level::{filter_to_u8, level_index, LEVEL_NAMES};
+use crate::poison::Poison;
+use crate::rate_limit::RateLimits;
+use crate::recent::Recent;
+use crate::route::Route;
+use crate::sample::Sampling;
+use crate::sync::{Mutex, RwLock};
+#[cfg(feature = "std")]
+use crate::worker::{Overflow, Worker};
+#[cfg(feature = "kv")]
+use crate::KvCallback;
+use crate::{
+    Callback, ErrorCallback, FfiCallback, FfiLogger, FilterCallback, FlushCallback, FormatCallback,
+    InteriorNul, Rejected, TimestampFormat, ALL_LEVELS, DEFAULT_CEILING,
+};
+
+/// Builder for an [FfiLogger], collecting the callback and its options.
+///
+/// ```
+/// use std::ffi::{c_char, c_void};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLogger};
+/// use log::LevelFilter;
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+///     0
+/// }
+///
+/// let logger = unsafe {
+///     FfiLogger::builder(FfiCallback::Message(sink))
+///         .level(LevelFilter::Warn)
+///         .build()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct FfiLoggerBuilder {
+    logger: FfiCallback,
+    data: Option<NonNull<c_void>>,
+    level: LevelFilter,
+    #[cfg(feature = "kv")]
+    kv: Option<KvCallback>,
+    timestamps: Option<TimestampFormat>,
+    sequence: bool,
+    #[cfg(feature = "json")]
+    json: bool,
+    flush: Option<FlushCallback>,
+    filter: Option<FilterCallback>,
+    formatter: Option<Formatter>,
+    component: Option<&'static CStr>,
+    newline: bool,
+    separator: &'static CStr,
+    level_names: [&'static CStr; 5],
+    #[cfg(feature = "std")]
+    stderr_fallback: bool,
+    #[cfg(feature = "std")]
+    thread_data: bool,
+    shutdown_notification: bool,
+    error: Option<ErrorCallback>,
+    audit: Option<(Callback, Option<NonNull<c_void>>)>,
+    rejected: Rejected,
+    backpressure: Option<(u32, Duration)>,
+    rate_limits: Vec<(Level, u32)>,
+    sampling: [u32; 5],
+    deduplicate: bool,
+    level_callbacks: [Option<FfiCallback>; 5],
+    #[cfg(feature = "std")]
+    asynchronous: Option<(usize, Overflow)>,
+    #[cfg(feature = "std")]
+    deinit_timeout: Duration,
+    interior_nul: InteriorNul,
+    retry_short_writes: bool,
+    #[cfg(feature = "std")]
+    process_id: bool,
+    max_len: Option<usize>,
+    ceiling: usize,
+    buffer_size: Option<usize>,
+    capacity: Option<usize>,
+    recent: Option<usize>,
+    batch: (usize, Option<Duration>),
+    ansi_colors: bool,
+}
+
+impl FfiLoggerBuilder {
+    /// Start building a logger that delivers messages through the callback.
+    ///
+    /// By default there is no user data, and every level is passed on.
+    pub fn new(logger: FfiCallback) -> FfiLoggerBuilder {
+        Self {
+            logger,
+            data: None,
+            level: LevelFilter::Trace,
+            #[cfg(feature = "kv")]
+            kv: None,
+            timestamps: None,
+            sequence: false,
+            #[cfg(feature = "json")]
+            json: false,
+            flush: None,
+            filter: None,
+            formatter: None,
+            component: None,
+            newline: false,
+            separator: c" ",
+            level_names: LEVEL_NAMES,
+            #[cfg(feature = "std")]
+            stderr_fallback: false,
+            #[cfg(feature = "std")]
+            thread_data: false,
+            shutdown_notification: false,
+            error: None,
+            audit: None,
+            rejected: Rejected::Drop,
+            backpressure: None,
+            rate_limits: Vec::new(),
+            sampling: [1; 5],
+            deduplicate: false,
+            level_callbacks: [None; 5],
+            #[cfg(feature = "std")]
+            asynchronous: None,
+            #[cfg(feature = "std")]
+            deinit_timeout: Duration::from_secs(5),
+            interior_nul: InteriorNul::default(),
+            retry_short_writes: false,
+            #[cfg(feature = "std")]
+            process_id: false,
+            max_len: None,
+            ceiling: DEFAULT_CEILING,
+            buffer_size: None,
+            capacity: None,
+            recent: None,
+            batch: (1, None),
+            ansi_colors: false,
+        }
+    }
+
+    /// The type-erased user data passed to each call of the callback.
+    pub fn data(mut self, data: Option<NonNull<c_void>>) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// The initial level filter of the logger. This can be changed later with
+    /// [LogHandle::set_level](crate::LogHandle::set_level).
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Whether to read the system clock for each record and pass the time in
+    /// [FfiLogRecord::timestamp](crate::FfiLogRecord::timestamp). This is off by default to avoid
+    /// the cost of reading the clock when it is not used.
+    ///
+    /// This is the same as [timestamp_format](Self::timestamp_format) with
+    /// [TimestampFormat::Numeric] when enabled.
+    pub fn timestamps(mut self, enabled: bool) -> Self {
+        self.timestamps = enabled.then_some(TimestampFormat::Numeric);
+        self
+    }
+
+    /// Read the system clock for each record, and deliver the time as given. This enables
+    /// [timestamps](Self::timestamps), which are passed in
+    /// [FfiLogRecord::timestamp](crate::FfiLogRecord::timestamp) either way.
+    ///
+    /// With [TimestampFormat::Rfc3339] the time is also written before the message, and before
+    /// the colored level with [ansi_colors](Self::ansi_colors). It is not written into
+    /// [json](Self::json) records, which always have a numeric `timestamp` field. Without a
+    /// clock, the time is always the Unix epoch.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, TimestampFormat};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     let message = unsafe { CStr::from_ptr(message) }.to_str().unwrap();
+    ///     let (time, message) = message.split_once(' ').unwrap();
+    ///     assert_eq!(time.len(), "2024-05-01T12:34:56.789Z".len());
+    ///     assert!(time.ends_with('Z'));
+    ///     assert_eq!(message, "hello");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .timestamp_format(TimestampFormat::Rfc3339)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("hello");
+    /// ```
+    pub fn timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamps = Some(format);
+        self
+    }
+
+    /// Whether to number each record and pass the number in
+    /// [FfiLogRecord::sequence](crate::FfiLogRecord::sequence).
+    ///
+    /// Numbers start at `1` and increase by one for every record given to the logger, including
+    /// ones it filters out by level or drops, so a gap means records were not delivered. Records
+    /// are numbered when they reach the logger, so a sink may still receive them out of order
+    /// when several threads log at once. This is off by default to avoid the cost of the shared
+    /// counter, in which case `0` is passed.
+    ///
+    /// ```
+    /// use std::ffi::c_void;
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogRecord, FfiLogger, LogHandle};
+    /// use log::LevelFilter;
+    ///
+    /// static LAST: AtomicU64 = AtomicU64::new(0);
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+    ///     LAST.store(unsafe { (*record).sequence }, Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Record(sink))
+    ///         .sequence_numbers(true)
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(LevelFilter::Trace);
+    /// LogHandle::new(logger).set_level(LevelFilter::Info);
+    ///
+    /// log::info!("first");
+    /// assert_eq!(LAST.load(Ordering::Relaxed), 1);
+    ///
+    /// log::debug!("filtered out");
+    /// log::info!("third");
+    /// assert_eq!(LAST.load(Ordering::Relaxed), 3);
+    /// ```
+    pub fn sequence_numbers(mut self, enabled: bool) -> Self {
+        self.sequence = enabled;
+        self
+    }
+
+    /// Whether to deliver each record as a JSON object instead of the bare message.
+    ///
+    /// The object has `level`, `target`, `message`, and `timestamp` fields, with the timestamp
+    /// being the same as [FfiLogRecord::timestamp](crate::FfiLogRecord::timestamp). Timestamps
+    /// are always read for JSON records, regardless of [FfiLoggerBuilder::timestamps].
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     let message = unsafe { CStr::from_ptr(message) }.to_str().unwrap();
+    ///     let (message, _timestamp) = message.rsplit_once(',').unwrap();
+    ///     assert_eq!(
+    ///         message,
+    ///         r#"{"level":"WARN","target":"app","message":"say \"hi\"\n\\\u0001""#,
+    ///     );
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe { FfiLogger::builder(FfiCallback::Message(sink)).json(true).build() };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::warn!(target: "app", "say \"hi\"\n\\{}", '\x01');
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json(mut self, enabled: bool) -> Self {
+        self.json = enabled;
+        self
+    }
+
+    /// Whether to color the level of each record with ANSI escape sequences, for sinks that
+    /// write to a terminal.
+    ///
+    /// The level is placed before the message, followed by the [separator](Self::separator), and
+    /// is the only part that is colored. For JSON records the value of the `level` field is
+    /// colored instead. This is off by default so that output to files or pipes is not cluttered
+    /// with escape sequences.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"\x1b[31mERROR\x1b[0m on fire");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .ansi_colors(true)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::error!("on fire");
+    /// ```
+    pub fn ansi_colors(mut self, enabled: bool) -> Self {
+        self.ansi_colors = enabled;
+        self
+    }
+
+    /// Deliver records of the level through this callback instead of the logger's own one.
+    ///
+    /// The callback receives the same user data as the logger's own callback, and is used for as
+    /// long as that one is set, including after it is replaced with
+    /// [LogHandle::set_callback](crate::LogHandle::set_callback). Levels without a callback of
+    /// their own use the logger's callback. Sinks added with
+    /// [LogHandle::add_sink](crate::LogHandle::add_sink) are not affected.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    /// use log::Level;
+    ///
+    /// static ERRORS: AtomicUsize = AtomicUsize::new(0);
+    /// static OTHERS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// extern "C" fn errors(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     ERRORS.fetch_add(1, Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// extern "C" fn others(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     OTHERS.fetch_add(1, Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(others))
+    ///         .level_callback(Level::Error, FfiCallback::Message(errors))
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::error!("on fire");
+    /// log::warn!("smoking");
+    /// log::info!("fine");
+    /// assert_eq!(ERRORS.load(Ordering::Relaxed), 1);
+    /// assert_eq!(OTHERS.load(Ordering::Relaxed), 2);
+    /// ```
+    pub fn level_callback(mut self, level: Level, logger: FfiCallback) -> Self {
+        self.level_callbacks[level_index(level)] = Some(logger);
+        self
+    }
+
+    /// A callback to flush the sink when the logger is flushed, such as through
+    /// [LogHandle::flush](crate::LogHandle::flush). It receives the same user data as the main
+    /// callback.
+    ///
+    /// Without one, flushing does nothing, as each message is expected to be flushed by the
+    /// main callback. The flush callback is only called when a callback was called since the
+    /// last flush, unless forced with [LogHandle::force_flush](crate::LogHandle::force_flush).
+    pub fn flush_callback(mut self, flush: FlushCallback) -> Self {
+        self.flush = Some(flush);
+        self
+    }
+
+    /// A callback deciding whether each record is logged, for filtering rules that are easier
+    /// to express on the other side. It receives the same user data as the main callback.
+    ///
+    /// It is only called for records that pass the level and target filters, before their
+    /// message is formatted.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"kept");
+    ///     0
+    /// }
+    ///
+    /// extern "C" fn filter(_: Option<NonNull<c_void>>, _: FfiLevel, target: *const u8, len: usize) -> bool {
+    ///     let target = unsafe { std::slice::from_raw_parts(target, len) };
+    ///     target != b"noisy"
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .filter(filter)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!(target: "noisy", "skipped");
+    /// log::info!("kept");
+    /// ```
+    pub fn filter(mut self, filter: FilterCallback) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Write the message for each record with the formatter, instead of the built-in format.
+    ///
+    /// The formatter is passed its own user data. Its output is delivered to every callback as
+    /// it is, so [timestamp_format](Self::timestamp_format), [json](Self::json),
+    /// [ansi_colors](Self::ansi_colors), [max_message_len](Self::max_message_len), and
+    /// [LogHandle::set_context](crate::LogHandle::set_context) do not change it. The message
+    /// passed to the formatter is still cut off at the
+    /// [message_ceiling](Self::message_ceiling), and both it and the output are cut off to fit
+    /// into a [fixed_buffer](Self::fixed_buffer). The built-in format is used for records the
+    /// formatter fails on. See
+    /// [FormatCallback](crate::FormatCallback) for an example.
+    pub fn formatter(mut self, format: FormatCallback, data: Option<NonNull<c_void>>) -> Self {
+        self.formatter = Some(Formatter { format, data });
+        self
+    }
+
+    /// Name the component logging through this logger, such as the library it belongs to, so
+    /// that its records can be told apart from those of other libraries in the same process.
+    ///
+    /// The component is passed as [FfiLogRecord::component](crate::FfiLogRecord::component), and
+    /// never changes.
+    ///
+    /// ```
+    /// use std::ffi::{c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogRecord, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+    ///     let component = unsafe { CStr::from_ptr((*record).component) };
+    ///     assert_eq!(component, c"libcodec");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Record(sink))
+    ///         .component(c"libcodec")
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("decoded a frame");
+    /// ```
+    pub fn component(mut self, component: &'static CStr) -> Self {
+        self.component = Some(component);
+        self
+    }
+
+    /// Pass the id of the process as [FfiLogRecord::process_id](crate::FfiLogRecord::process_id),
+    /// such as to tell apart the records of several processes writing to the same place. Off by
+    /// default.
+    ///
+    /// The id is read once when the logger is built, as it never changes. It is `0` on
+    /// `wasm32-unknown-unknown`, which has no processes. This requires the `std` feature.
+    ///
+    /// ```
+    /// use std::ffi::c_void;
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogRecord, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+    ///     assert_eq!(unsafe { (*record).process_id }, std::process::id());
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Record(sink))
+    ///         .process_id(true)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("from this process");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn process_id(mut self, enabled: bool) -> Self {
+        self.process_id = enabled;
+        self
+    }
+
+    /// End each message with a newline, such as for sinks writing messages to a file as they
+    /// are. Off by default.
+    ///
+    /// The newline comes after everything else, including the colors of
+    /// [ansi_colors](Self::ansi_colors) and the object of [json](Self::json), and is included in
+    /// the length passed to the callbacks. It does not count towards
+    /// [max_message_len](Self::max_message_len), and is not kept in the
+    /// [recent_messages](Self::recent_messages).
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"written\n");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .newline(true)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("written");
+    /// ```
+    pub fn newline(mut self, enabled: bool) -> Self {
+        self.newline = enabled;
+        self
+    }
+
+    /// What follows each field placed before the message, which is a single space by default.
+    ///
+    /// This applies to the time of [timestamp_format](Self::timestamp_format), the level of
+    /// [ansi_colors](Self::ansi_colors), and the colon after the context of
+    /// [LogHandle::set_context](crate::LogHandle::set_context), such as for sinks that split
+    /// fields on tabs or pipes. The separator is a C string, so it cannot contain null bytes.
+    /// It is not used by [json](Self::json) or a [formatter](Self::formatter).
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     let message = unsafe { CStr::from_ptr(message) };
+    ///     assert_eq!(message, c"\x1b[33mWARN\x1b[0m\tdecoder:\tframe dropped");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .ansi_colors(true)
+    ///         .separator(c"\t")
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// LogHandle::new(logger).set_context("decoder");
+    /// log::warn!("frame dropped");
+    /// ```
+    pub fn separator(mut self, separator: &'static CStr) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// The names of the levels, from [Level::Error](log::Level::Error) to
+    /// [Level::Trace](log::Level::Trace), as placed before the message by
+    /// [ansi_colors](Self::ansi_colors), in the `level` field of [json](Self::json), and in
+    /// messages written to stderr by [stderr_fallback](Self::stderr_fallback). These are the
+    /// uppercase names of [log::Level] by default, such as `WARN`.
+    ///
+    /// The names are C strings, so they cannot contain null bytes. Callbacks are still passed
+    /// the [FfiLevel](crate::FfiLevel) of each record, whatever its name.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     let message = unsafe { CStr::from_ptr(message) };
+    ///     assert_eq!(message, c"\x1b[33mW\x1b[0m disk almost full");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .ansi_colors(true)
+    ///         .level_names([c"E", c"W", c"I", c"D", c"T"])
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::warn!("disk almost full");
+    /// ```
+    pub fn level_names(mut self, names: [&'static CStr; 5]) -> Self {
+        self.level_names = names;
+        self
+    }
+
+    /// Write messages to stderr while the logger has no callback, such as after
+    /// [LogHandle::deinit](crate::LogHandle::deinit), instead of losing them. Off by default.
+    ///
+    /// This is meant for debugging, so the messages only hold the level, target and message of
+    /// each record, without the other formatting options. Writing does not allocate, and
+    /// failing to write is ignored. This can also be changed later with
+    /// [LogHandle::set_stderr_fallback](crate::LogHandle::set_stderr_fallback). Records logged
+    /// before any logger is registered with [log] never reach this one, so they are still lost.
+    /// This requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn stderr_fallback(mut self, enabled: bool) -> Self {
+        self.stderr_fallback = enabled;
+        self
+    }
+
+    /// Pass the logger's callback the user data set for the thread logging each record with
+    /// [LogHandle::set_thread_data](crate::LogHandle::set_thread_data), instead of the logger's
+    /// own user data. Threads without any set are still passed the logger's data. Off by
+    /// default.
+    ///
+    /// This is for sinks keeping state for each thread, such as a buffer, which would otherwise
+    /// need a thread-local of their own. The data is looked up when the record is logged, so an
+    /// [asynchronous](Self::asynchronous) logger still passes the data of the thread that
+    /// logged it. Sinks added with [LogHandle::add_sink](crate::LogHandle::add_sink) are always
+    /// passed their own data. This requires the `std` feature.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// extern "C" fn sink(data: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     let lines = unsafe { data.unwrap().cast::<Vec<u32>>().as_mut() };
+    ///     lines.push(lines.len() as u32);
+    ///     0
+    /// }
+    ///
+    /// let shared = NonNull::from(Box::leak(Box::new(Vec::<u32>::new())));
+    /// let handle = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .data(Some(shared.cast()))
+    ///         .thread_data(true)
+    ///         .build()
+    /// }
+    /// .leak_and_register()
+    /// .unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// std::thread::spawn(move || {
+    ///     let mut own = Vec::<u32>::new();
+    ///     unsafe { handle.set_thread_data(Some(NonNull::from(&mut own).cast())) };
+    ///     log::info!("to this thread's lines");
+    ///     log::info!("and again");
+    ///     unsafe { handle.set_thread_data(None) };
+    ///     assert_eq!(own, [0, 1]);
+    /// })
+    /// .join()
+    /// .unwrap();
+    ///
+    /// log::info!("to the shared lines");
+    /// assert_eq!(unsafe { shared.as_ref() }, &[0]);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn thread_data(mut self, enabled: bool) -> Self {
+        self.thread_data = enabled;
+        self
+    }
+
+    /// Call the callback one last time with a null message when
+    /// [LogHandle::deinit](crate::LogHandle::deinit) is called, so that the sink can flush and
+    /// close its output before the user data is reclaimed. Off by default.
+    ///
+    /// A [RecordCallback](crate::RecordCallback) is passed a null record instead. A
+    /// [BufferCallback](crate::BufferCallback) or [RawCallback](crate::RawCallback) is passed
+    /// [FfiLevel::Info](crate::FfiLevel::Info) and a length of zero along with the null message.
+    /// A [SyslogCallback](crate::SyslogCallback) is passed the severity of `Info`, and a
+    /// [ContextCallback](crate::ContextCallback) the current context. The return value is
+    /// ignored. Sinks added with
+    /// [LogHandle::add_sink](crate::LogHandle::add_sink) are not notified.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+    ///
+    /// static CLOSED: AtomicBool = AtomicBool::new(false);
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     if message.is_null() {
+    ///         CLOSED.store(true, Ordering::Relaxed);
+    ///     }
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .shutdown_notification(true)
+    ///         .build()
+    /// };
+    /// let handle = LogHandle::new(Box::leak(Box::new(logger)));
+    ///
+    /// handle.deinit();
+    /// assert!(CLOSED.load(Ordering::Relaxed));
+    /// ```
+    pub fn shutdown_notification(mut self, notify: bool) -> Self {
+        self.shutdown_notification = notify;
+        self
+    }
+
+    /// A callback told about each message that is dropped instead of delivered, so that lost
+    /// messages can be noticed. It receives the same user data as the main callback.
+    ///
+    /// These are the messages counted by
+    /// [LogHandle::dropped_count](crate::LogHandle::dropped_count). Each one is reported, even
+    /// while the callback is running on other threads, except for those dropped on a thread by
+    /// the callback itself, such as by logging.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::fmt;
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger};
+    ///
+    /// static REPORTED: AtomicBool = AtomicBool::new(false);
+    ///
+    /// struct Faulty;
+    ///
+    /// impl fmt::Display for Faulty {
+    ///     fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         panic!("oops")
+    ///     }
+    /// }
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     0
+    /// }
+    ///
+    /// extern "C" fn dropped(_: Option<NonNull<c_void>>, level: FfiLevel, reason: *const c_char) {
+    ///     assert_eq!(level, FfiLevel::Info);
+    ///     assert_eq!(unsafe { CStr::from_ptr(reason) }, c"formatting the message panicked");
+    ///     REPORTED.store(true, Ordering::Relaxed);
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .error_callback(dropped)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("{}", Faulty);
+    /// assert!(REPORTED.load(Ordering::Relaxed));
+    /// # }
+    /// ```
+    pub fn error_callback(mut self, error: ErrorCallback) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// A callback passed every record before any of the logger's filtering, with its own user
+    /// data, such as for security logging that must never be dropped.
+    ///
+    /// This is called before the level and target filters, the level mask, the filter callback,
+    /// rate limiting, and deduplication, none of which apply to it, and even while the logger is
+    /// [muted](crate::LogHandle::set_enabled). Records filtered out by [log::max_level] never
+    /// reach the logger, and so are not passed to it either. Its return value is ignored. The
+    /// message is formatted the same way as for the main callback.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    /// use log::LevelFilter;
+    ///
+    /// static AUDITED: AtomicUsize = AtomicUsize::new(0);
+    /// static LOGGED: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// extern "C" fn audit(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     AUDITED.fetch_add(1, Ordering::Relaxed);
+    ///     0
+    /// }
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     LOGGED.fetch_add(1, Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// let handle = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .level(LevelFilter::Warn)
+    ///         .audit_callback(audit, None)
+    ///         .build()
+    /// }
+    /// .leak_and_register()
+    /// .unwrap();
+    /// log::set_max_level(LevelFilter::Trace);
+    ///
+    /// log::warn!("kept");
+    /// log::debug!("filtered");
+    /// handle.set_enabled(false);
+    /// log::error!("muted");
+    /// assert_eq!(AUDITED.load(Ordering::Relaxed), 3);
+    /// assert_eq!(LOGGED.load(Ordering::Relaxed), 1);
+    /// ```
+    pub fn audit_callback(mut self, audit: Callback, data: Option<NonNull<c_void>>) -> Self {
+        self.audit = Some((audit, data));
+        self
+    }
+
+    /// What is done with records the logger filters out, which are skipped without a trace by
+    /// default. Counting or forwarding them shows how much logging is being suppressed.
+    ///
+    /// This applies to records filtered out by the level and target filters, the level mask,
+    /// and the filter callback. Those filtered out by [log::max_level] never reach the logger,
+    /// and nothing is done with them, nor with records logged while the logger is
+    /// [muted](crate::LogHandle::set_enabled). Forwarded messages are formatted the same way as
+    /// for the main callback, and what the callback returns is ignored.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle, Rejected};
+    /// use log::LevelFilter;
+    ///
+    /// static FORWARDED: AtomicBool = AtomicBool::new(false);
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"kept");
+    ///     0
+    /// }
+    /// extern "C" fn rejected(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"suppressed");
+    ///     FORWARDED.store(true, Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .level(LevelFilter::Warn)
+    ///         .rejected(Rejected::Forward(rejected, None))
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(LevelFilter::Debug);
+    ///
+    /// log::warn!("kept");
+    /// log::info!("suppressed");
+    /// log::trace!("not even seen");
+    /// assert!(FORWARDED.load(Ordering::Relaxed));
+    /// assert_eq!(LogHandle::new(logger).rejected_count(), 1);
+    /// ```
+    pub fn rejected(mut self, rejected: Rejected) -> Self {
+        self.rejected = rejected;
+        self
+    }
+
+    /// How interior null bytes in messages are handled before being passed as C strings.
+    ///
+    /// By default they are replaced with the ASCII substitute character (`0x1A`), which some
+    /// tools treat as the end of a file. Either another replacement byte can be chosen, or the
+    /// message can be cut off at the first null byte instead.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, InteriorNul};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"before");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .interior_nul(InteriorNul::Truncate)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("before{}after", '\0');
+    /// ```
+    pub fn interior_nul(mut self, interior_nul: InteriorNul) -> Self {
+        self.interior_nul = interior_nul;
+        self
+    }
+
+    /// Cut messages longer than `bytes` off at a character boundary, ending them with `...`
+    /// instead, so that they fit into a fixed-size buffer on the other side.
+    ///
+    /// The limit does not include the null terminator. With
+    /// [ansi_colors](Self::ansi_colors) it includes the colored level, and with
+    /// [json](Self::json) it only applies to the message field before it is escaped. Cut off
+    /// messages are counted by [LogHandle::truncated_count](crate::LogHandle::truncated_count).
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"h\u{e9}...");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .max_message_len(7)
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    /// let handle = LogHandle::new(logger);
+    ///
+    /// // The second `\u{e9}` would not fit before the ellipsis.
+    /// log::info!("h\u{e9}\u{e9}llo");
+    /// assert_eq!(handle.truncated_count(), 1);
+    /// ```
+    pub fn max_message_len(mut self, bytes: usize) -> Self {
+        self.max_len = Some(bytes);
+        self
+    }
+
+    /// Stop formatting messages longer than `bytes`, which is one MiB by default, so that a
+    /// `Display` implementation writing without end cannot use up the memory of the process.
+    ///
+    /// This is a safeguard rather than a limit for the consumer, which
+    /// [max_message_len](Self::max_message_len) is for, and applies even without one. Messages
+    /// reaching it are cut off like for `max_message_len`, but are counted by
+    /// [LogHandle::capped_count](crate::LogHandle::capped_count) instead.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::fmt;
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+    ///
+    /// struct Endless;
+    ///
+    /// impl fmt::Display for Endless {
+    ///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         loop {
+    ///             f.write_str("spam ")?;
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     let message = unsafe { CStr::from_ptr(message) }.to_bytes();
+    ///     assert_eq!(message.len(), 4096);
+    ///     assert!(message.ends_with(b"..."));
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .message_ceiling(4096)
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    /// let handle = LogHandle::new(logger);
+    ///
+    /// log::info!("{}", Endless);
+    /// assert_eq!(handle.capped_count(), 1);
+    /// assert_eq!(handle.truncated_count(), 0);
+    /// ```
+    pub fn message_ceiling(mut self, bytes: usize) -> Self {
+        self.ceiling = bytes;
+        self
+    }
+
+    /// Call a callback receiving the length of the message again with the rest of it when it
+    /// returns that it only wrote part of it, until it is all written, the callback writes
+    /// nothing, or it returns an error. Off by default, in which case short writes are only
+    /// counted by [LogHandle::short_writes](crate::LogHandle::short_writes).
+    ///
+    /// This applies to [BufferCallback](crate::BufferCallback),
+    /// [RawCallback](crate::RawCallback), [SyslogCallback](crate::SyslogCallback), and the
+    /// `WideCallback` of Windows, which are passed a pointer into the rest of the message along
+    /// with its length. Only turn this
+    /// on for callbacks that return how much they wrote, as many return `0` on success.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::Mutex;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger, LogHandle};
+    ///
+    /// static WRITTEN: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+    ///
+    /// // Writes at most four bytes at a time, like a pipe that is almost full.
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: FfiLevel, message: *const c_char, len: usize) -> isize {
+    ///     let len = len.min(4);
+    ///     let bytes = unsafe { std::slice::from_raw_parts(message.cast::<u8>(), len) };
+    ///     WRITTEN.lock().unwrap().extend_from_slice(bytes);
+    ///     len as isize
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Buffer(sink))
+    ///         .retry_short_writes(true)
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("all of it, eventually");
+    /// assert_eq!(*WRITTEN.lock().unwrap(), b"all of it, eventually");
+    /// assert_eq!(LogHandle::new(logger).short_writes(), 0);
+    /// ```
+    pub fn retry_short_writes(mut self, enabled: bool) -> Self {
+        self.retry_short_writes = enabled;
+        self
+    }
+
+    /// Format messages into a buffer of `size` bytes that each thread allocates once, so that
+    /// logging does not allocate afterwards. Messages that do not fit, including their null
+    /// terminator, are cut off as with [max_message_len](Self::max_message_len).
+    ///
+    /// The callbacks are passed a pointer into the buffer, so the message is always
+    /// null-terminated within `size` bytes, which must be at least one. Nothing else is allocated
+    /// when delivering to a [Callback](crate::Callback), [ContextCallback](crate::ContextCallback),
+    /// [IdCallback](crate::IdCallback), [BufferCallback](crate::BufferCallback),
+    /// [RawCallback](crate::RawCallback), or [SyslogCallback](crate::SyslogCallback), as long
+    /// as messages have no interior null bytes to replace. A
+    /// [RecordCallback](crate::RecordCallback), an [OwnedCallback](crate::OwnedCallback),
+    /// [deduplicate](Self::deduplicate),
+    /// [asynchronous](Self::asynchronous) logging, and key-value callbacks still allocate, and
+    /// with [json](Self::json) only the message field is limited. A
+    /// [formatter](Self::formatter) is given no more room than is left in the buffer, and its
+    /// output is cut off there instead of growing it, which counts towards
+    /// [LogHandle::truncated_count](crate::LogHandle::truncated_count). Without the `std` feature
+    /// there are no thread-locals, so a buffer is allocated for each message instead.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: FfiLevel, message: *const c_char, len: usize) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"a lo...");
+    ///     assert_eq!(len + 1, 8);
+    ///     len as isize
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Buffer(sink))
+    ///         .fixed_buffer(8)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("a long message");
+    /// ```
+    pub fn fixed_buffer(mut self, size: usize) -> Self {
+        self.buffer_size = Some(size.max(1));
+        self
+    }
+
+    /// Keep the last `capacity` messages, so that they can be retrieved with
+    /// [LogHandle::drain_recent](crate::LogHandle::drain_recent) or
+    /// [ffi_logger_copy_recent](crate::ffi::ffi_logger_copy_recent), such as for a crash report.
+    ///
+    /// Messages are kept after being formatted, whatever the callbacks do with them, and even
+    /// while there is nowhere to deliver them. Filtered out messages are not kept.
+    pub fn recent_messages(mut self, capacity: usize) -> Self {
+        self.recent = Some(capacity);
+        self
+    }
+
+    /// Reserve `bytes` for the buffer each thread formats messages into up front, so that a
+    /// burst of logging does not grow it.
+    ///
+    /// The buffer of the thread calling [build](Self::build) is reserved right away, and that
+    /// of any other thread the first time it logs. Unlike [fixed_buffer](Self::fixed_buffer),
+    /// longer messages are not cut off, and grow the buffer instead. The queue of an
+    /// [asynchronous](Self::asynchronous) logger and the slots of
+    /// [recent_messages](Self::recent_messages) are always allocated when the logger is built,
+    /// but the messages they hold, and those of batches, are still allocated as they are
+    /// logged. Without the `std` feature there are no thread-locals, so this does nothing.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .with_capacity(4096)
+    ///         .recent_messages(64)
+    ///         .build()
+    /// };
+    /// ```
+    pub fn with_capacity(mut self, bytes: usize) -> Self {
+        self.capacity = Some(bytes);
+        self
+    }
+
+    /// Collect records for [FfiCallback::Batch] callbacks until there are `max_records` of
+    /// them, or until a record is logged `max_delay` after the oldest one. By default each
+    /// record is delivered on its own.
+    ///
+    /// The delay is only checked when a record is logged, so records can be pending for longer
+    /// if nothing else is logged, until the logger is flushed. Without a clock, such as on
+    /// `wasm32-unknown-unknown` or without the `std` feature, only the number of records is
+    /// checked. See [BatchCallback](crate::BatchCallback) for an example.
+    pub fn batch(mut self, max_records: usize, max_delay: Duration) -> Self {
+        self.batch = (max_records, Some(max_delay));
+        self
+    }
+
+    /// Stop calling the callbacks for a while once they return errors for `threshold` records
+    /// in a row, so that a busy sink does not hold up the code that is logging.
+    ///
+    /// Records logged during the `cooldown` are dropped and counted by
+    /// [LogHandle::dropped_count](crate::LogHandle::dropped_count). Afterwards delivery is tried
+    /// again. Backing off requires a clock, so it is never done on `wasm32-unknown-unknown`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use std::time::Duration;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+    ///
+    /// extern "C" fn busy(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     -16
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(busy))
+    ///         .backpressure(2, Duration::from_secs(60))
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// for _ in 0..5 {
+    ///     log::info!("are you there?");
+    /// }
+    /// assert_eq!(LogHandle::new(logger).dropped_count(), 3);
+    /// # }
+    /// ```
+    pub fn backpressure(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.backpressure = Some((threshold, cooldown));
+        self
+    }
+
+    /// Deliver at most `per_second` records of the level each second, suppressing the rest.
+    ///
+    /// Records use up tokens that are refilled evenly over each second, so short bursts of up to
+    /// `per_second` records are still delivered. When a record is delivered after some were
+    /// suppressed, a record of the same level saying how many were suppressed is delivered
+    /// before it, with the target `ffi_logger`. It is also delivered when the logger is flushed,
+    /// so that a burst followed by silence is still reported. Setting a level again replaces its
+    /// limit, and a limit of zero suppresses every record of the level. Limiting requires a
+    /// clock, so it is never done on `wasm32-unknown-unknown` or without the `std` feature.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    /// use std::sync::Mutex;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    /// use log::Level;
+    ///
+    /// static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     let message = unsafe { CStr::from_ptr(message) }.to_str().unwrap();
+    ///     MESSAGES.lock().unwrap().push(message.to_owned());
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .rate_limit(Level::Error, 2)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// for i in 0..5 {
+    ///     log::error!("storm {i}");
+    /// }
+    /// std::thread::sleep(std::time::Duration::from_millis(600));
+    /// log::error!("calm");
+    /// for i in 0..2 {
+    ///     log::error!("storm again {i}");
+    /// }
+    /// log::logger().flush();
+    ///
+    /// assert_eq!(
+    ///     *MESSAGES.lock().unwrap(),
+    ///     ["storm 0", "storm 1", "suppressed 3 messages", "calm", "suppressed 2 messages"],
+    /// );
+    /// # }
+    /// ```
+    pub fn rate_limit(mut self, level: Level, per_second: u32) -> Self {
+        self.rate_limits.push((level, per_second));
+        self
+    }
+
+    /// Keep only one in every `keep_one_in` records of the level that pass the filters,
+    /// dropping the rest, such as to still see something of a hot path logging at
+    /// [Level::Debug](log::Level::Debug) without all of it.
+    ///
+    /// The first record of the level is kept, and then every `keep_one_in`th one after it,
+    /// whatever its target. Dropped records are counted by
+    /// [LogHandle::sampled_count](crate::LogHandle::sampled_count). Every level keeps all of its
+    /// records by default, which a rate of `1` or `0` goes back to. Sampling is done before
+    /// [rate_limit](Self::rate_limit), so a rate limit only counts the records that were kept.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    /// use log::Level;
+    ///
+    /// static DELIVERED: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     DELIVERED.fetch_add(1, Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// let handle = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .level(log::LevelFilter::Debug)
+    ///         .sample(Level::Debug, 100)
+    ///         .build()
+    /// }
+    /// .leak_and_register()
+    /// .unwrap();
+    /// log::set_max_level(log::LevelFilter::Debug);
+    ///
+    /// for i in 0..1000 {
+    ///     log::debug!("iteration {i}");
+    /// }
+    /// log::error!("never sampled");
+    ///
+    /// assert_eq!(DELIVERED.load(Ordering::Relaxed), 11);
+    /// assert_eq!(handle.sampled_count(), 990);
+    /// ```
+    pub fn sample(mut self, level: Level, keep_one_in: u32) -> Self {
+        self.sampling[level_index(level)] = keep_one_in;
+        self
+    }
+
+    /// Whether to collapse runs of identical records into one, followed by a record saying how
+    /// many times it was repeated.
+    ///
+    /// Records are identical when their level, target, and message are. The number of repeats
+    /// is reported with the target `ffi_logger` once a different record is logged, or when the
+    /// logger is flushed. This is off by default, as each message is compared against the
+    /// previous one. The message is still only formatted once, and the bytes compared are the
+    /// ones delivered.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    /// use std::sync::Mutex;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     let message = unsafe { CStr::from_ptr(message) }.to_str().unwrap();
+    ///     MESSAGES.lock().unwrap().push(message.to_owned());
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .deduplicate(true)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// for _ in 0..3 {
+    ///     log::warn!("disk full");
+    /// }
+    /// log::info!("disk cleaned");
+    ///
+    /// assert_eq!(
+    ///     *MESSAGES.lock().unwrap(),
+    ///     ["disk full", "last message repeated 2 times", "disk cleaned"],
+    /// );
+    /// ```
+    pub fn deduplicate(mut self, enabled: bool) -> Self {
+        self.deduplicate = enabled;
+        self
+    }
+
+    /// Deliver records from a worker thread, so that the code logging them does not wait on
+    /// slow callbacks.
+    ///
+    /// Records are formatted on the thread logging them, and then queued for the worker thread,
+    /// which calls the callbacks. Up to `capacity` records can be queued, after which the
+    /// `overflow` policy applies. Key-value pairs are still passed to the
+    /// [KvCallback](crate::KvCallback) before queuing the record.
+    ///
+    /// Flushing the logger waits for every queued record to be delivered, or for at most a
+    /// timeout with [LogHandle::flush_timeout](crate::LogHandle::flush_timeout), and
+    /// [LogHandle::deinit](crate::LogHandle::deinit) also stops the worker thread. Records are
+    /// delivered on the thread logging them when there is no worker thread, such as after it was
+    /// stopped or if it could not be spawned.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle, Overflow};
+    ///
+    /// static DELIVERED: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// extern "C" fn slow(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     std::thread::sleep(std::time::Duration::from_millis(10));
+    ///     DELIVERED.fetch_add(1, Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(slow))
+    ///         .asynchronous(16, Overflow::Block)
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// for i in 0..5 {
+    ///     log::info!("queued {i}");
+    /// }
+    ///
+    /// let handle = LogHandle::new(logger);
+    /// handle.flush();
+    /// assert_eq!(DELIVERED.load(Ordering::Relaxed), 5);
+    /// handle.deinit();
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn asynchronous(mut self, capacity: usize, overflow: Overflow) -> Self {
+        self.asynchronous = Some((capacity, overflow));
+        self
+    }
+
+    /// How long [LogHandle::deinit](crate::LogHandle::deinit) waits for the worker thread of an
+    /// [asynchronous](Self::asynchronous) logger to deliver the queued records, which is five
+    /// seconds by default. [Duration::MAX] waits for as long as it takes.
+    ///
+    /// If the worker thread is not done by then, such as because a callback is stuck, it is
+    /// left to finish on its own. Once the callback is taken away by `deinit` the records still
+    /// queued are not delivered to it, but a call to it already in progress may still be using
+    /// its data.
+    #[cfg(feature = "std")]
+    pub fn deinit_timeout(mut self, timeout: Duration) -> Self {
+        self.deinit_timeout = timeout;
+        self
+    }
+
+    /// Also pass the structured key-value pairs of each record to a separate callback.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     0
+    /// }
+    ///
+    /// extern "C" fn pair(_: Option<NonNull<c_void>>, key: *const c_char, value: *const c_char) {
+    ///     let key = unsafe { CStr::from_ptr(key) };
+    ///     let value = unsafe { CStr::from_ptr(value) };
+    ///     assert_eq!((key.to_str(), value.to_str()), (Ok("id"), Ok("42")));
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .kv_callback(pair)
+    ///         .build()
+    /// };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!(id = 42; "request handled");
+    /// ```
+    #[cfg(feature = "kv")]
+    pub fn kv_callback(mut self, callback: KvCallback) -> Self {
+        self.kv = Some(callback);
+        self
+    }
+
+    /// Create the logger.
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply.
+    pub unsafe fn build(self) -> FfiLogger {
+        let Self {
+            logger,
+            data,
+            level,
+            #[cfg(feature = "kv")]
+            kv,
+            timestamps,
+            sequence,
+            #[cfg(feature = "json")]
+            json,
+            flush,
+            filter,
+            formatter,
+            component,
+            newline,
+            separator,
+            level_names,
+            #[cfg(feature = "std")]
+            stderr_fallback,
+            #[cfg(feature = "std")]
+            thread_data,
+            shutdown_notification,
+            error,
+            audit,
+            rejected,
+            backpressure,
+            rate_limits: limits,
+            sampling,
+            deduplicate,
+            level_callbacks,
+            #[cfg(feature = "std")]
+            asynchronous,
+            #[cfg(feature = "std")]
+            deinit_timeout,
+            interior_nul,
+            retry_short_writes,
+            #[cfg(feature = "std")]
+            process_id,
+            max_len,
+            ceiling,
+            buffer_size,
+            capacity,
+            recent,
+            batch,
+            ansi_colors,
+        } = self;
+
+        #[cfg(feature = "std")]
+        if let Some(capacity) = capacity {
+            buffer::reserve(capacity);
+        }
+
+        // The null terminator, and any newline, have to fit into the buffer as well.
+        let fixed_room = buffer_size.map(|size| size.saturating_sub(1 + usize::from(newline)));
+
+        let mut rate_limits = RateLimits::default();
+        for (level, per_second) in limits {
+            rate_limits.set(level, per_second);
+        }
+
+        let mut dispatcher = Dispatcher::new(
+            Route { logger, data },
+            level_callbacks,
+            interior_nul,
+            backpressure.map(|(threshold, cooldown)| Backpressure::new(threshold, cooldown)),
+            error,
+            audit,
+            Batch::new(batch.0, batch.1),
+        );
+        dispatcher.retry_short_writes = retry_short_writes;
+        #[cfg(feature = "std")]
+        if process_id {
+            dispatcher.process_id = crate::process_id();
+        }
+        dispatcher.rejected = rejected;
+        let dispatcher = Arc::new(dispatcher);
+        #[cfg(feature = "std")]
+        let worker = asynchronous.and_then(|(capacity, overflow)| {
+            Worker::spawn(
+                dispatcher.clone(),
+                capacity,
+                overflow,
+                deinit_timeout,
+                component,
+            )
+            .ok()
+        });
+
+        FfiLogger {
+            dispatcher,
+            #[cfg(feature = "std")]
+            worker,
+            #[cfg(feature = "kv")]
+            kv,
+            timestamps,
+            sequence: sequence.then(|| AtomicU64::new(1)),
+            #[cfg(feature = "json")]
+            json,
+            flush,
+            filter,
+            formatter,
+            component,
+            newline,
+            separator,
+            level_names,
+            #[cfg(feature = "std")]
+            stderr_fallback: AtomicBool::new(stderr_fallback),
+            #[cfg(feature = "std")]
+            thread_data,
+            level_counts: Default::default(),
+            poison: Poison::default(),
+            shutdown_notification,
+            rate_limits,
+            sampling: Sampling::new(sampling),
+            dedup: deduplicate.then(Dedup::default),
+            ansi_colors,
+            max_len: match (max_len, fixed_room) {
+                (Some(max_len), Some(room)) => Some(max_len.min(room)),
+                (max_len, room) => max_len.or(room),
+            },
+            ceiling,
+            buffer_size: buffer_size.max(capacity),
+            fixed_room,
+            level: AtomicU8::new(filter_to_u8(level)),
+            on: AtomicBool::new(true),
+            sets_max_level: AtomicBool::new(false),
+            level_mask: AtomicU8::new(ALL_LEVELS),
+            target_levels: TargetLevels::default(),
+            target_lists: TargetLists::default(),
+            context: RwLock::new(None),
+            recent: recent.map(Recent::new),
+            owned: Mutex::new(None),
+            closure: None,
+        }
+    }
+}
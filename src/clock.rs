@@ -0,0 +1,101 @@
+use core::fmt;
+#[cfg(all(
+    feature = "std",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+use std::sync::OnceLock;
+#[cfg(all(
+    feature = "std",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The current time in nanoseconds since the Unix epoch, or `0` if the system clock is set
+/// before it.
+#[cfg(all(
+    feature = "std",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+pub(crate) fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64)
+}
+
+/// Nanoseconds since an arbitrary point early in the process, which never go backwards.
+#[cfg(all(
+    feature = "std",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+pub(crate) fn monotonic_nanos() -> Option<u64> {
+    static START: OnceLock<Instant> = OnceLock::new();
+
+    Some(START.get_or_init(Instant::now).elapsed().as_nanos() as u64)
+}
+
+// There are no clocks without the standard library, or without an OS where reading them
+// panics instead.
+
+#[cfg(any(
+    not(feature = "std"),
+    all(target_family = "wasm", target_os = "unknown")
+))]
+pub(crate) fn unix_nanos() -> u64 {
+    0
+}
+
+#[cfg(any(
+    not(feature = "std"),
+    all(target_family = "wasm", target_os = "unknown")
+))]
+pub(crate) fn monotonic_nanos() -> Option<u64> {
+    None
+}
+
+/// How timestamps are delivered, as set by
+/// [FfiLoggerBuilder::timestamp_format](crate::FfiLoggerBuilder::timestamp_format).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TimestampFormat {
+    /// Only pass the nanoseconds since the Unix epoch in
+    /// [FfiLogRecord::timestamp](crate::FfiLogRecord::timestamp).
+    Numeric,
+    /// Also write the time in UTC before the message as an RFC 3339 string with millisecond
+    /// precision, such as `2024-05-01T12:34:56.789Z`, followed by the
+    /// [separator](crate::FfiLoggerBuilder::separator).
+    Rfc3339,
+}
+
+/// Write the nanoseconds since the Unix epoch as an RFC 3339 timestamp in UTC.
+pub(crate) fn write_rfc3339(f: &mut impl fmt::Write, nanos: u64) -> fmt::Result {
+    let seconds = nanos / 1_000_000_000;
+    let millis = nanos % 1_000_000_000 / 1_000_000;
+    let (year, month, day) = civil_from_days(seconds / 86_400);
+    let seconds = seconds % 86_400;
+
+    write!(
+        f,
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{millis:03}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    )
+}
+
+/// The proleptic Gregorian date of the days since the Unix epoch, using the algorithm from
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Shift the epoch to 0000-03-01, so that leap days are at the end of each year.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
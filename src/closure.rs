@@ -0,0 +1,72 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::ffi::c_void;
+use core::fmt;
+use core::ptr::NonNull;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+
+use log::Level;
+
+use crate::FfiLevel;
+
+type Closure = dyn Fn(Level, &str) + Send + Sync;
+
+/// A closure owned by the logger, which is passed to [trampoline] as its user data.
+pub(crate) struct OwnedClosure(NonNull<Box<Closure>>);
+
+// Safety: The closure is required to be thread-safe, and is only freed on drop.
+unsafe impl Send for OwnedClosure {}
+unsafe impl Sync for OwnedClosure {}
+
+impl OwnedClosure {
+    pub(crate) fn new(f: impl Fn(Level, &str) + Send + Sync + 'static) -> OwnedClosure {
+        let f: Box<Closure> = Box::new(f);
+        Self(NonNull::from(Box::leak(Box::new(f))))
+    }
+
+    pub(crate) fn data(&self) -> NonNull<c_void> {
+        self.0.cast()
+    }
+}
+
+impl Drop for OwnedClosure {
+    fn drop(&mut self) {
+        // Safety: The pointer came from `Box::leak`, and the logger owning this cannot be used
+        // anymore.
+        drop(unsafe { Box::from_raw(self.0.as_ptr()) });
+    }
+}
+
+impl fmt::Debug for OwnedClosure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OwnedClosure").finish_non_exhaustive()
+    }
+}
+
+/// Calls the closure given as the user data, returning `-1` if it panicked.
+pub(crate) extern "C" fn trampoline(
+    data: Option<NonNull<c_void>>,
+    level: FfiLevel,
+    message: *const u8,
+    len: usize,
+) -> isize {
+    let Some(data) = data else {
+        return -1;
+    };
+    // Safety: The data is only ever an `OwnedClosure` that outlives the logger's routes.
+    let f = unsafe { data.cast::<Box<Closure>>().as_ref() };
+    // Safety: The message is valid for the duration of the call.
+    let message = unsafe { core::slice::from_raw_parts(message, len) };
+    // The message is only ever formatted from strings, so this does not allocate.
+    let message = String::from_utf8_lossy(message);
+
+    #[cfg(feature = "std")]
+    if panic::catch_unwind(AssertUnwindSafe(|| f(level.into(), &message))).is_err() {
+        return -1;
+    }
+    #[cfg(not(feature = "std"))]
+    f(level.into(), &message);
+
+    len as isize
+}
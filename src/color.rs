@@ -0,0 +1,15 @@
+use log::Level;
+
+/// Resets the terminal to its default style.
+pub(crate) const RESET: &str = "\x1b[0m";
+
+/// The ANSI SGR sequence setting the color used for the level, matching `env_logger`.
+pub(crate) fn sgr(level: Level) -> &'static str {
+    match level {
+        Level::Error => "\x1b[31m",
+        Level::Warn => "\x1b[33m",
+        Level::Info => "\x1b[32m",
+        Level::Debug => "\x1b[34m",
+        Level::Trace => "\x1b[36m",
+    }
+}
@@ -0,0 +1,36 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use log::LevelFilter;
+
+/// The settings of an [FfiLogger](crate::FfiLogger) that can be changed through a
+/// [LogHandle](crate::LogHandle), saved by [LogHandle::snapshot](crate::LogHandle::snapshot) to
+/// be restored with [LogHandle::apply](crate::LogHandle::apply).
+///
+/// This holds no pointers, so it can be kept around for as long as needed, such as while a
+/// plugin is reloaded. The callbacks and their data are not part of it, nor is
+/// [log::max_level], which is not specific to the logger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FfiLoggerConfig {
+    /// As set by [LogHandle::set_level](crate::LogHandle::set_level).
+    pub level: LevelFilter,
+    /// As set by [LogHandle::set_enabled](crate::LogHandle::set_enabled).
+    pub enabled: bool,
+    /// As set by [LogHandle::set_level_mask](crate::LogHandle::set_level_mask).
+    pub level_mask: u8,
+    /// Each target given a level by
+    /// [LogHandle::set_target_level](crate::LogHandle::set_target_level), and its level.
+    pub target_levels: Vec<(String, LevelFilter)>,
+    /// As set by [LogHandle::set_target_allowlist](crate::LogHandle::set_target_allowlist).
+    pub target_allowlist: Vec<String>,
+    /// As set by [LogHandle::set_target_denylist](crate::LogHandle::set_target_denylist).
+    pub target_denylist: Vec<String>,
+    /// As set by [LogHandle::set_context](crate::LogHandle::set_context), or [None] if there is
+    /// no context.
+    pub context: Option<String>,
+    /// As set by [LogHandle::set_stderr_fallback](crate::LogHandle::set_stderr_fallback). This
+    /// requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub stderr_fallback: bool,
+}
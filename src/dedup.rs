@@ -0,0 +1,77 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use log::Level;
+
+use crate::sync::Mutex;
+
+/// Collapses runs of identical messages, counting how many times the last one was repeated.
+#[derive(Debug, Default)]
+pub(crate) struct Dedup {
+    last: Mutex<Option<Last>>,
+}
+
+#[derive(Debug)]
+struct Last {
+    level: Level,
+    target: String,
+    message: Vec<u8>,
+    repeats: u64,
+}
+
+/// Whether a message was the same as the previous one.
+pub(crate) enum Seen {
+    /// The message should not be delivered.
+    Repeat,
+    /// The message should be delivered, after reporting that the previous message was repeated,
+    /// if it was.
+    New(Option<(Level, u64)>),
+}
+
+impl Dedup {
+    /// Compare the message to the previous one, which is the same only if the level and target
+    /// are also the same.
+    pub(crate) fn see(&self, level: Level, target: &str, message: &[u8]) -> Seen {
+        let mut last = self.last.lock();
+
+        match &mut *last {
+            Some(last)
+                if last.level == level && last.target == target && last.message == message =>
+            {
+                last.repeats += 1;
+                Seen::Repeat
+            }
+            Some(last) => {
+                let repeated = (last.repeats > 0).then_some((last.level, last.repeats));
+                last.level = level;
+                last.target.clear();
+                last.target.push_str(target);
+                last.message.clear();
+                last.message.extend_from_slice(message);
+                last.repeats = 0;
+                Seen::New(repeated)
+            }
+            None => {
+                *last = Some(Last {
+                    level,
+                    target: target.into(),
+                    message: message.to_vec(),
+                    repeats: 0,
+                });
+                Seen::New(None)
+            }
+        }
+    }
+
+    /// Take the number of times the last message was repeated, so that it can be reported
+    /// without waiting for a different message.
+    pub(crate) fn take_repeats(&self) -> Option<(Level, u64)> {
+        let mut last = self.last.lock();
+        let last = last.as_mut()?;
+
+        match core::mem::take(&mut last.repeats) {
+            0 => None,
+            repeats => Some((last.level, repeats)),
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use alloc::ffi::CString;
+use core::ffi::{c_char, c_void, CStr};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicPtr, AtomicUsize, Ordering};
+
+use log::{Level, Record};
+
+use crate::backpressure::Backpressure;
+use crate::batch::Batch;
+use crate::buffer::Message;
+use crate::level::level_index;
+use crate::record::{self, FfiLogRecord};
+use crate::route::{Route, Routes, SharedRoutes};
+use crate::thread;
+use crate::{target, to_c_string, Callback, ErrorCallback, FfiCallback, FfiLevel, InteriorNul};
+
+/// What is done with records the logger filters out, set with
+/// [FfiLoggerBuilder::rejected](crate::FfiLoggerBuilder::rejected).
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Rejected {
+    /// Skip them without a trace.
+    #[default]
+    Drop,
+    /// Count them in [LogHandle::rejected_count](crate::LogHandle::rejected_count).
+    Count,
+    /// Count them, and also pass their messages to this callback with its own user data.
+    Forward(Callback, Option<NonNull<c_void>>),
+}
+
+/// Delivers formatted messages through the routes, and keeps track of how that went.
+///
+/// This is shared with the worker thread of an asynchronous logger, so it only holds what is
+/// needed to call the callbacks.
+#[derive(Debug)]
+pub(crate) struct Dispatcher {
+    pub(crate) routes: SharedRoutes,
+    /// The callbacks used instead of the logger's own callback for each level, indexed by its
+    /// discriminant minus one.
+    level_callbacks: [Option<FfiCallback>; 5],
+    pub(crate) interior_nul: InteriorNul,
+    /// Whether callbacks receiving a length are called again with the rest of a message they
+    /// only wrote part of.
+    pub(crate) retry_short_writes: bool,
+    /// The id of the process passed in records, or zero if it is not passed.
+    pub(crate) process_id: u32,
+    backpressure: Option<Backpressure>,
+    error_callback: Option<ErrorCallback>,
+    /// The callback passed every record before any filtering, with its own user data.
+    audit: Option<(Callback, Option<NonNull<c_void>>)>,
+    /// The records waiting to be delivered to batch callbacks.
+    batch: Batch,
+    pub(crate) rejected: Rejected,
+    /// The second pointer passed to [FfiCallback::Context] callbacks.
+    context: AtomicPtr<c_void>,
+    /// The most recent negative value returned by the callback, or zero if there was none.
+    pub(crate) last_error: AtomicIsize,
+    /// The number of times a callback returned an error.
+    pub(crate) errors: AtomicUsize,
+    /// Whether the most recent call of a callback returned success, or `true` before any.
+    pub(crate) last_ok: AtomicBool,
+    /// Whether a callback was called since the logger was last flushed.
+    pub(crate) dirty: AtomicBool,
+    pub(crate) short_writes: AtomicUsize,
+    /// The number of records filtered out, if they are counted.
+    pub(crate) rejected_count: AtomicUsize,
+    /// The number of messages cut off for being longer than the limit.
+    pub(crate) truncated: AtomicUsize,
+    /// The number of messages cut off for being longer than the ceiling, which is higher.
+    pub(crate) capped: AtomicUsize,
+    /// The number of messages lost because formatting them panicked, because of backpressure,
+    /// because the queue of an asynchronous logger was full, or because they were logged from
+    /// within a callback.
+    pub(crate) dropped: AtomicUsize,
+}
+
+// Safety: The callbacks & data are required to be thread-safe by the constructors, and by the
+// `LogHandle` methods that replace them.
+unsafe impl Send for Dispatcher {}
+unsafe impl Sync for Dispatcher {}
+
+/// What is passed to the callbacks about a record, besides its message and strings.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Header {
+    pub(crate) level: Level,
+    pub(crate) line: u32,
+    pub(crate) timestamp: u64,
+    pub(crate) sequence: u64,
+    pub(crate) thread_id: u64,
+    /// The null-terminated name of the thread that logged the record, or null if it is unnamed.
+    pub(crate) thread_name: *const c_char,
+    pub(crate) kv_count: usize,
+    /// The null-terminated component of the logger, or null if it has none.
+    pub(crate) component: *const c_char,
+    /// The user data of the thread that logged the record, which replaces that of the logger's
+    /// own callback.
+    pub(crate) thread_data: Option<NonNull<c_void>>,
+    pub(crate) process_id: u32,
+}
+
+/// The metadata of a record as C strings, for [FfiLogRecord].
+#[derive(Debug)]
+pub(crate) struct RecordStrings {
+    pub(crate) target: CString,
+    pub(crate) module_path: Option<CString>,
+    pub(crate) file: Option<CString>,
+}
+
+impl RecordStrings {
+    pub(crate) fn new(record: &Record) -> RecordStrings {
+        Self {
+            target: to_c_string(target(record)),
+            module_path: record.module_path().map(to_c_string),
+            file: record.file().map(to_c_string),
+        }
+    }
+
+    /// The record passed to callbacks, pointing into these strings and the message.
+    pub(crate) fn record(&self, header: &Header, message: &CStr) -> FfiLogRecord {
+        FfiLogRecord {
+            size: record::SIZE,
+            version: record::FFI_LOG_RECORD_VERSION,
+            level: header.level.into(),
+            message: message.as_ptr(),
+            message_len: message.to_bytes().len(),
+            target: self.target.as_ptr(),
+            module_path: self
+                .module_path
+                .as_deref()
+                .map_or(ptr::null(), CStr::as_ptr),
+            file: self.file.as_deref().map_or(ptr::null(), CStr::as_ptr),
+            line: header.line,
+            timestamp: header.timestamp,
+            sequence: header.sequence,
+            thread_id: header.thread_id,
+            thread_name: header.thread_name,
+            kv_count: header.kv_count,
+            component: header.component,
+            process_id: header.process_id,
+        }
+    }
+}
+
+impl Dispatcher {
+    pub(crate) fn new(
+        primary: Route,
+        level_callbacks: [Option<FfiCallback>; 5],
+        interior_nul: InteriorNul,
+        backpressure: Option<Backpressure>,
+        error_callback: Option<ErrorCallback>,
+        audit: Option<(Callback, Option<NonNull<c_void>>)>,
+        batch: Batch,
+    ) -> Dispatcher {
+        Self {
+            routes: SharedRoutes::new(primary),
+            level_callbacks,
+            interior_nul,
+            retry_short_writes: false,
+            process_id: 0,
+            backpressure,
+            error_callback,
+            audit,
+            batch,
+            rejected: Rejected::Drop,
+            context: AtomicPtr::new(ptr::null_mut()),
+            last_error: AtomicIsize::new(0),
+            errors: AtomicUsize::new(0),
+            last_ok: AtomicBool::new(true),
+            dirty: AtomicBool::new(false),
+            short_writes: AtomicUsize::new(0),
+            rejected_count: AtomicUsize::new(0),
+            truncated: AtomicUsize::new(0),
+            capped: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn context(&self) -> Option<NonNull<c_void>> {
+        NonNull::new(self.context.load(Ordering::Acquire))
+    }
+
+    /// Set the context passed to [FfiCallback::Context] callbacks, returning the previous one.
+    pub(crate) fn set_context(&self, context: Option<NonNull<c_void>>) -> Option<NonNull<c_void>> {
+        let context = context.map_or(ptr::null_mut(), NonNull::as_ptr);
+        NonNull::new(self.context.swap(context, Ordering::AcqRel))
+    }
+
+    /// Whether there is nowhere to deliver a message of the level to right now, dropping the
+    /// message if that is because of backpressure.
+    pub(crate) fn is_unavailable(&self, level: Level) -> bool {
+        if self.routes.load().iter().next().is_none() {
+            return true;
+        }
+        if let Some(backpressure) = &self.backpressure {
+            if backpressure.is_backing_off() {
+                self.drop_message(level, c"dropped while backing off from a failing sink");
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Whether there is any callback to deliver messages to.
+    #[cfg(feature = "std")]
+    pub(crate) fn has_routes(&self) -> bool {
+        self.routes.load().iter().next().is_some()
+    }
+
+    /// Count a message of the level as dropped, and report why to the error callback.
+    pub(crate) fn drop_message(&self, level: Level, reason: &'static CStr) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+
+        let Some(callback) = self.error_callback else {
+            return;
+        };
+        // The error callback is not called again if it drops a message itself, such as by
+        // logging.
+        let Some(_reporting) = thread::report() else {
+            return;
+        };
+        let data = self.routes.load().primary.and_then(|primary| primary.data);
+        callback(data, level.into(), reason.as_ptr());
+    }
+
+    pub(crate) fn has_audit(&self) -> bool {
+        self.audit.is_some()
+    }
+
+    /// Pass the message to the audit callback, if there is one. What it returns is ignored, so
+    /// that it does not affect delivery to the other callbacks.
+    pub(crate) fn audit(&self, message: &mut Message) {
+        if let Some((audit, data)) = self.audit {
+            audit(data, message.c_str(self.interior_nul).as_ptr());
+        }
+    }
+
+    /// Pass the message of a record that was filtered out to the callback for them, if there
+    /// is one. What it returns is ignored.
+    pub(crate) fn reject(&self, message: &mut Message) {
+        if let Rejected::Forward(callback, data) = self.rejected {
+            callback(data, message.c_str(self.interior_nul).as_ptr());
+        }
+    }
+
+    /// The routes a message of the level is delivered through, with the callback for the level
+    /// in place of the logger's own callback if there is one, and the given user data in place
+    /// of its data.
+    fn routes_for<'r>(
+        &self,
+        routes: &'r Routes,
+        level: Level,
+        data: Option<NonNull<c_void>>,
+    ) -> impl Iterator<Item = Route> + 'r {
+        let primary = routes.primary.map(|primary| Route {
+            logger: self.level_callbacks[level_index(level)].unwrap_or(primary.logger),
+            data: data.or(primary.data),
+        });
+
+        primary.into_iter().chain(routes.sinks())
+    }
+
+    /// Whether every callback of the level receives the exact bytes of messages, so that they
+    /// are never needed as C strings.
+    pub(crate) fn only_raw(&self, level: Level) -> bool {
+        self.routes_for(&self.routes.load(), level, None)
+            .all(|route| matches!(route.logger, FfiCallback::Raw(_)))
+    }
+
+    /// Pass the message to each callback, making the strings of the record only if a callback
+    /// needs them.
+    pub(crate) fn dispatch(
+        &self,
+        header: &Header,
+        strings: impl FnOnce() -> RecordStrings,
+        message: &mut Message,
+    ) {
+        let mut make_strings = Some(strings);
+        let mut strings = None;
+        let mut failed = false;
+        let mut batched = false;
+
+        let routes = self.routes.load();
+        for Route { logger, data } in self.routes_for(&routes, header.level, header.thread_data) {
+            let (written, len) = match logger {
+                FfiCallback::Message(logger) => {
+                    let message = message.c_str(self.interior_nul);
+                    let len = message.to_bytes().len();
+
+                    (logger(data, message.as_ptr()), len)
+                }
+                FfiCallback::Context(logger) => {
+                    let message = message.c_str(self.interior_nul);
+                    let len = message.to_bytes().len();
+
+                    (logger(data, self.context(), message.as_ptr()), len)
+                }
+                FfiCallback::Id(logger, id) => {
+                    let message = message.c_str(self.interior_nul);
+                    let len = message.to_bytes().len();
+
+                    (logger(id, message.as_ptr()), len)
+                }
+                FfiCallback::Owned(logger) => {
+                    let message = CString::from(message.c_str(self.interior_nul));
+                    let len = message.to_bytes().len();
+
+                    (logger(data, message.into_raw()), len)
+                }
+                FfiCallback::Record(logger) => {
+                    let message = message.c_str(self.interior_nul);
+                    let len = message.to_bytes().len();
+                    let strings: &RecordStrings =
+                        strings.get_or_insert_with(|| (make_strings.take().unwrap())());
+
+                    let record = strings.record(header, message);
+
+                    (logger(data, &record), len)
+                }
+                FfiCallback::Buffer(logger) => {
+                    let message = message.c_str(self.interior_nul);
+                    let len = message.to_bytes().len();
+
+                    let written = self.write_all(len, |offset| {
+                        let rest = message.as_ptr().wrapping_add(offset);
+                        logger(data, header.level.into(), rest, len - offset)
+                    });
+                    (written, len)
+                }
+                FfiCallback::Syslog(logger) => {
+                    let message = message.c_str(self.interior_nul);
+                    let len = message.to_bytes().len();
+                    let severity = FfiLevel::from(header.level).syslog_severity();
+
+                    let written = self.write_all(len, |offset| {
+                        let rest = message.as_ptr().wrapping_add(offset);
+                        logger(data, severity.into(), rest, len - offset)
+                    });
+                    (written, len)
+                }
+                #[cfg(windows)]
+                FfiCallback::Wide(logger) => {
+                    let message = message.wide(self.interior_nul);
+                    let len = message.len() - 1;
+
+                    let written = self.write_all(len, |offset| {
+                        let rest = message.as_ptr().wrapping_add(offset);
+                        logger(data, header.level.into(), rest, len - offset)
+                    });
+                    (written, len)
+                }
+                FfiCallback::Raw(logger) => {
+                    let message = message.raw();
+
+                    let written = self.write_all(message.len(), |offset| {
+                        let rest = &message[offset..];
+                        logger(data, header.level.into(), rest.as_ptr(), rest.len())
+                    });
+                    (written, message.len())
+                }
+                FfiCallback::Batch(_) => {
+                    batched = true;
+                    continue;
+                }
+            };
+
+            // Every sink is called, even if an earlier one failed.
+            failed |= self.check_written(written, len);
+        }
+
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.record(failed);
+        }
+
+        if batched {
+            let strings = strings.unwrap_or_else(|| (make_strings.take().unwrap())());
+            if self
+                .batch
+                .push(header, strings, message.c_str(self.interior_nul))
+            {
+                self.deliver_batch();
+            }
+        }
+    }
+
+    /// Pass the bytes to each [FfiCallback::Raw] callback, returning how many there were.
+    pub(crate) fn dispatch_raw(&self, level: Level, bytes: &[u8]) -> usize {
+        let mut delivered = 0;
+        for Route { logger, data } in self.routes_for(&self.routes.load(), level, None) {
+            if let FfiCallback::Raw(logger) = logger {
+                let written = logger(data, level.into(), bytes.as_ptr(), bytes.len());
+                self.check_written(written, bytes.len());
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    /// Pass the message to each callback that can receive it without anything being allocated
+    /// or locked, returning how many there were.
+    pub(crate) fn dispatch_static(&self, level: Level, message: &'static CStr) -> usize {
+        let len = message.to_bytes().len();
+
+        let routes = self.routes.load_signal_safe();
+        let mut delivered = 0;
+        for Route { logger, data } in self.routes_for(&routes, level, None) {
+            let written = match logger {
+                FfiCallback::Message(logger) => logger(data, message.as_ptr()),
+                FfiCallback::Context(logger) => logger(data, self.context(), message.as_ptr()),
+                FfiCallback::Id(logger, id) => logger(id, message.as_ptr()),
+                FfiCallback::Buffer(logger) => logger(data, level.into(), message.as_ptr(), len),
+                FfiCallback::Raw(logger) => {
+                    logger(data, level.into(), message.as_ptr().cast(), len)
+                }
+                FfiCallback::Syslog(logger) => {
+                    let severity = FfiLevel::from(level).syslog_severity();
+                    logger(data, severity.into(), message.as_ptr(), len)
+                }
+                _ => continue,
+            };
+            self.check_written(written, len);
+            delivered += 1;
+        }
+        delivered
+    }
+
+    /// Pass the pending records to each batch callback, if there are any.
+    pub(crate) fn deliver_batch(&self) {
+        self.batch.take(|records| {
+            let mut failed = false;
+            for &Route { logger, data } in self.routes.load().iter() {
+                if let FfiCallback::Batch(logger) = logger {
+                    let written = logger(data, records.as_ptr(), records.len());
+                    failed |= self.check_written(written, records.len());
+                }
+            }
+
+            if let Some(backpressure) = &self.backpressure {
+                backpressure.record(failed);
+            }
+        });
+    }
+
+    /// Call the function with the offset to write from, and again with the rest of the message
+    /// while it writes only part of it, if short writes are retried. Returns the total written,
+    /// or the first error.
+    fn write_all(&self, len: usize, mut write: impl FnMut(usize) -> isize) -> isize {
+        let mut total = 0;
+        loop {
+            let written = write(total);
+            match usize::try_from(written) {
+                Ok(written) if self.retry_short_writes && written > 0 && total + written < len => {
+                    total += written;
+                }
+                Ok(written) => return (total + written) as isize,
+                Err(_) => return written,
+            }
+        }
+    }
+
+    /// Record short writes and errors from the value a callback returned for a message of the
+    /// given length, returning whether it was an error.
+    fn check_written(&self, written: isize, len: usize) -> bool {
+        let failed = match usize::try_from(written) {
+            Ok(written) => {
+                if written < len {
+                    self.short_writes.fetch_add(1, Ordering::Relaxed);
+                }
+                false
+            }
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                self.last_error.store(written, Ordering::Relaxed);
+                true
+            }
+        };
+        self.last_ok.store(!failed, Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Relaxed);
+        failed
+    }
+}
@@ -0,0 +1,19 @@
+use core::fmt;
+
+/// Why an [FfiLogger](crate::FfiLogger) could not be created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FfiLoggerError {
+    /// The callback was a null function pointer.
+    NullCallback,
+}
+
+impl fmt::Display for FfiLoggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FfiLoggerError::NullCallback => f.write_str("the logging callback is null"),
+        }
+    }
+}
+
+impl core::error::Error for FfiLoggerError {}
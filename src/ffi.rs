@@ -0,0 +1,701 @@
+//! Functions for controlling logging directly from C.
+//!
+//! These are exported unmangled, so a C host can declare and call them when the Rust library is
+//! linked in:
+//!
+//! ```c
+//! #include <stdbool.h>
+//! #include <stddef.h>
+//! #include <stdint.h>
+//!
+//! typedef enum {
+//!     FFI_INIT_STATUS_OK = 0,
+//!     FFI_INIT_STATUS_ALREADY_INITIALIZED = 1,
+//!     FFI_INIT_STATUS_INVALID_ARGUMENT = 2,
+//! } FfiInitStatus;
+//!
+//! typedef ptrdiff_t (*FfiLoggerCallback)(void*, const char*);
+//! typedef void (*FfiLoggerKvCallback)(void*, const char*, const char*);
+//!
+//! // Levels: 0 = off, 1 = error, 2 = warn, 3 = info, 4 = debug, 5 = trace.
+//! FfiInitStatus ffi_logger_init(FfiLoggerCallback callback, void* data, uint8_t level);
+//! bool ffi_logger_set_max_level(uint8_t level);
+//! uint8_t ffi_logger_max_level(void);
+//! uint8_t ffi_logger_effective_level(void);
+//! bool ffi_logger_is_active(void);
+//! bool ffi_logger_flush(void);
+//! bool ffi_logger_set_level_mask(uint8_t mask);
+//! bool ffi_logger_set_enabled(bool enabled);
+//! bool ffi_logger_set_stderr_fallback(bool enabled);
+//! void ffi_logger_set_panic_hook(bool enabled);
+//! bool ffi_logger_set_thread_name(const char* name);
+//! size_t ffi_logger_copy_recent(char* buffer, size_t len);
+//! bool ffi_logger_level_counts(uint64_t counts[5]);
+//! size_t ffi_logger_log_bytes(uint8_t level, const uint8_t* bytes, size_t len);
+//! bool ffi_logger_current_key_values(FfiLoggerKvCallback callback, void* data);
+//! bool ffi_logger_set_target_allowlist(const char* const* targets, size_t count);
+//! bool ffi_logger_set_target_denylist(const char* const* targets, size_t count);
+//! bool ffi_logger_set_filter(const char* spec);
+//! void ffi_logger_free_message(char* message);
+//!
+//! typedef struct FfiLogHandle FfiLogHandle;
+//!
+//! FfiLogHandle* ffi_logger_handle_new(void);
+//! void ffi_logger_handle_free(FfiLogHandle* handle);
+//! void* ffi_logger_handle_deinit(const FfiLogHandle* handle);
+//! void ffi_logger_handle_flush(const FfiLogHandle* handle);
+//! bool ffi_logger_handle_set_level(const FfiLogHandle* handle, uint8_t level);
+//! uint8_t ffi_logger_handle_max_level(const FfiLogHandle* handle);
+//! void ffi_logger_handle_set_enabled(const FfiLogHandle* handle, bool enabled);
+//! void ffi_logger_handle_set_level_mask(const FfiLogHandle* handle, uint8_t mask);
+//! bool ffi_logger_handle_is_active(const FfiLogHandle* handle);
+//! ```
+//!
+//! These use the same names as the header generated with the `cbindgen` feature,
+//! `ffi_logger.h`, which also has the types of the other callbacks and only declares the
+//! functions exported with the enabled features. Including it is preferred over declaring them
+//! by hand.
+//!
+//! Levels are passed as integers from `0`, meaning off, to `5`, meaning trace, in order of
+//! increasing verbosity. These values match the discriminants of [log::LevelFilter] and will
+//! not change.
+
+use alloc::boxed::Box;
+use alloc::ffi::CString;
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_void, CStr};
+use core::ptr::{self, NonNull};
+use core::slice;
+
+use crate::level::{filter_to_u8, level_filter};
+use crate::{Callback, FfiLogger, LogHandle};
+
+/// The result of [ffi_logger_init].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub enum FfiInitStatus {
+    /// The logger was registered.
+    Ok = 0,
+    /// Another logger was already registered with [log], so nothing was changed.
+    AlreadyInitialized = 1,
+    /// The callback was null or the level was invalid, so nothing was changed.
+    InvalidArgument = 2,
+}
+
+/// Register a logger calling the callback with [FfiLogger::init], setting the max level from
+/// the integer level.
+///
+/// The user data is not retained unless this returns [FfiInitStatus::Ok], and still belongs to
+/// the caller otherwise.
+///
+/// ```
+/// use std::ffi::{c_char, c_void};
+/// use std::ptr;
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::ffi::{ffi_logger_init, FfiInitStatus};
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+///     0
+/// }
+///
+/// unsafe {
+///     assert_eq!(ffi_logger_init(None, ptr::null_mut(), 3), FfiInitStatus::InvalidArgument);
+///     assert_eq!(ffi_logger_init(Some(sink), ptr::null_mut(), 6), FfiInitStatus::InvalidArgument);
+///     assert_eq!(ffi_logger_init(Some(sink), ptr::null_mut(), 3), FfiInitStatus::Ok);
+///     assert_eq!(
+///         ffi_logger_init(Some(sink), ptr::null_mut(), 3),
+///         FfiInitStatus::AlreadyInitialized
+///     );
+/// }
+/// ```
+///
+/// # Safety
+/// The same requirements as [FfiLogger::new] apply.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_init(
+    callback: Option<Callback>,
+    data: *mut c_void,
+    level: u8,
+) -> FfiInitStatus {
+    let (Some(callback), Some(level)) = (callback, level_filter(level)) else {
+        return FfiInitStatus::InvalidArgument;
+    };
+
+    match FfiLogger::init(callback, NonNull::new(data), level) {
+        Ok(_) => FfiInitStatus::Ok,
+        Err(_) => FfiInitStatus::AlreadyInitialized,
+    }
+}
+
+/// Set [log::set_max_level] from the integer level, returning whether it was valid.
+///
+/// An invalid level leaves the max level unchanged. Setting it to `0` is the way to disable
+/// logging before deallocating on the FFI side.
+///
+/// ```
+/// use ffi_logger::ffi::{ffi_logger_max_level, ffi_logger_set_max_level};
+///
+/// assert!(ffi_logger_set_max_level(2));
+/// assert_eq!(log::max_level(), log::LevelFilter::Warn);
+///
+/// assert!(!ffi_logger_set_max_level(6));
+/// assert_eq!(ffi_logger_max_level(), 2);
+///
+/// // The integer levels are those of `FfiLevel`, with `0` for off.
+/// for (filter, level) in log::LevelFilter::iter().zip(0..) {
+///     assert!(ffi_logger_set_max_level(level));
+///     assert_eq!(log::max_level(), filter);
+///     assert_eq!(ffi_logger_max_level(), level);
+/// }
+/// ```
+#[no_mangle]
+pub extern "C" fn ffi_logger_set_max_level(level: u8) -> bool {
+    match level_filter(level) {
+        Some(level) => {
+            log::set_max_level(level);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The current [log::max_level] as an integer level.
+#[no_mangle]
+pub extern "C" fn ffi_logger_max_level() -> u8 {
+    filter_to_u8(log::max_level())
+}
+
+/// The most verbose integer level that can currently reach the callback, so that a caller can
+/// skip preparing messages that would be filtered out.
+///
+/// This is [LogHandle::max_level] for the logger registered by [FfiLogger::init], or
+/// [log::max_level] if the logger was registered some other way.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub extern "C" fn ffi_logger_effective_level() -> u8 {
+    filter_to_u8(LogHandle::registered().map_or(log::max_level(), |handle| handle.max_level()))
+}
+
+/// Mute or unmute the logger registered by [FfiLogger::init], as with
+/// [LogHandle::set_enabled], returning `false` if there is no such logger.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub extern "C" fn ffi_logger_set_enabled(enabled: bool) -> bool {
+    LogHandle::registered()
+        .map(|handle| handle.set_enabled(enabled))
+        .is_some()
+}
+
+/// Set [LogHandle::set_level_mask] of the logger registered by [FfiLogger::init], returning
+/// `false` if there is no such logger.
+///
+/// Bit 0 of the mask is the error level, up to bit 4 for the trace level.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub extern "C" fn ffi_logger_set_level_mask(mask: u8) -> bool {
+    LogHandle::registered()
+        .map(|handle| handle.set_level_mask(mask))
+        .is_some()
+}
+
+/// Flush the logger registered by [FfiLogger::init] with [LogHandle::flush], waiting for the
+/// queue of an [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger to be delivered.
+///
+/// Returns `false` and does nothing if there is no such logger.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub extern "C" fn ffi_logger_flush() -> bool {
+    LogHandle::registered()
+        .map(|handle| handle.flush())
+        .is_some()
+}
+
+/// Turn [LogHandle::set_stderr_fallback] of the logger registered by [FfiLogger::init] on or
+/// off.
+///
+/// Returns `false` if there is no such logger. This requires the `std` feature.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[cfg(feature = "std")]
+#[no_mangle]
+pub extern "C" fn ffi_logger_set_stderr_fallback(enabled: bool) -> bool {
+    LogHandle::registered()
+        .map(|handle| handle.set_stderr_fallback(enabled))
+        .is_some()
+}
+
+/// Whether the logger registered by [FfiLogger::init] has anywhere to deliver messages to, as
+/// with [LogHandle::is_active].
+///
+/// This is `false` if there is no such logger.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub extern "C" fn ffi_logger_is_active() -> bool {
+    LogHandle::registered().is_some_and(|handle| handle.is_active())
+}
+
+/// Start or stop logging panics, as with [FfiLogger::install_panic_hook].
+///
+/// Stopping leaves only the panic hook that was set before the first time this was started.
+/// This requires the `std` feature.
+#[cfg(feature = "std")]
+#[no_mangle]
+pub extern "C" fn ffi_logger_set_panic_hook(enabled: bool) {
+    if enabled {
+        crate::panic_hook::enable();
+    } else {
+        crate::panic_hook::disable();
+    }
+}
+
+/// Set the name of the current thread passed to the callbacks, as with
+/// [FfiLogger::set_thread_name]. A null name goes back to the name the standard library knows.
+///
+/// Returns `false` if the name is not valid UTF-8, or it could not be set. This requires the
+/// `std` feature.
+///
+/// # Safety
+/// `name` must be null or a valid null-terminated string.
+#[cfg(feature = "std")]
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_set_thread_name(name: *const c_char) -> bool {
+    if name.is_null() {
+        return FfiLogger::set_thread_name(None);
+    }
+
+    match CStr::from_ptr(name).to_str() {
+        Ok(name) => FfiLogger::set_thread_name(Some(name)),
+        Err(_) => false,
+    }
+}
+
+/// Copy the messages kept by the logger registered by [FfiLogger::init] into the buffer of `len`
+/// bytes, each followed by a newline, from oldest to newest.
+///
+/// The buffer is always null-terminated, unless `len` is zero, even if the messages do not fit.
+/// Returns the length of all of the messages without the null terminator, so a return value of
+/// `len` or more means they were cut off. Unlike [LogHandle::drain_recent], the messages are
+/// still kept afterwards. This returns zero if there is no such logger, or if it does not keep
+/// messages, as set with [FfiLoggerBuilder::recent_messages].
+///
+/// ```
+/// # use std::ffi::{c_char, c_void, CStr};
+/// # use std::ptr::NonNull;
+/// use ffi_logger::ffi::ffi_logger_copy_recent;
+/// use ffi_logger::{FfiCallback, FfiLogger};
+///
+/// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+/// let logger = unsafe {
+///     FfiLogger::builder(FfiCallback::Message(sink))
+///         .recent_messages(8)
+///         .build()
+/// };
+/// logger.leak_and_register().unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+/// log::info!("first");
+/// log::info!("second");
+///
+/// let mut buffer = [0 as c_char; 8];
+/// assert_eq!(unsafe { ffi_logger_copy_recent(buffer.as_mut_ptr(), buffer.len()) }, 13);
+/// assert_eq!(unsafe { CStr::from_ptr(buffer.as_ptr()) }, c"first\ns");
+/// ```
+///
+/// # Safety
+/// `buffer` must be valid for writing `len` bytes, unless `len` is zero.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+/// [FfiLoggerBuilder::recent_messages]: crate::FfiLoggerBuilder::recent_messages
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_copy_recent(buffer: *mut c_char, len: usize) -> usize {
+    let Some(handle) = LogHandle::registered() else {
+        return 0;
+    };
+    let buffer = match len {
+        0 => &mut [],
+        len => slice::from_raw_parts_mut(buffer.cast::<u8>(), len),
+    };
+
+    handle.copy_recent(buffer)
+}
+
+/// Write [LogHandle::level_counts] of the logger registered by [FfiLogger::init] into the
+/// array, from error to trace.
+///
+/// Returns `false` and leaves the array unchanged if there is no such logger.
+///
+/// # Safety
+/// `counts` must be valid for writing five integers.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_level_counts(counts: *mut u64) -> bool {
+    let Some(handle) = LogHandle::registered() else {
+        return false;
+    };
+
+    counts.cast::<[u64; 5]>().write(handle.level_counts());
+    true
+}
+
+/// Deliver the bytes with [LogHandle::log_bytes] of the logger registered by
+/// [FfiLogger::init], at the integer level from `1`, meaning error, to `5`, meaning trace.
+///
+/// Returns how many callbacks received the bytes, which is zero if there is no such logger or
+/// the level is invalid.
+///
+/// # Safety
+/// `bytes` must be valid for reading `len` bytes, unless `len` is zero.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_log_bytes(level: u8, bytes: *const u8, len: usize) -> usize {
+    let (Some(handle), Some(level)) = (
+        LogHandle::registered(),
+        level_filter(level).and_then(|level| level.to_level()),
+    ) else {
+        return 0;
+    };
+    let bytes = match len {
+        0 => &[],
+        len => slice::from_raw_parts(bytes, len),
+    };
+
+    handle.log_bytes(level, bytes)
+}
+
+/// Pass each structured key-value pair of the record being delivered on the current thread to
+/// the callback, as with [FfiLoggerBuilder::kv_callback](crate::FfiLoggerBuilder::kv_callback).
+///
+/// This is meant to be called from within a callback, once [FfiLogRecord::kv_count] shows that
+/// there are pairs. Returns `false` if the callback is null or no record is being delivered on
+/// the current thread, such as by an [asynchronous](crate::FfiLoggerBuilder::asynchronous)
+/// logger or in a [batch](crate::BatchCallback). This requires the `kv` and `std` features.
+///
+/// ```
+/// use std::ffi::{c_char, c_void, CStr};
+/// use std::ptr::{self, NonNull};
+/// use std::sync::Mutex;
+///
+/// use ffi_logger::ffi::ffi_logger_current_key_values;
+/// use ffi_logger::{FfiCallback, FfiLogRecord, FfiLogger};
+///
+/// static PAIRS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+///
+/// extern "C" fn pair(_: Option<NonNull<c_void>>, key: *const c_char, value: *const c_char) {
+///     let (key, value) = unsafe { (CStr::from_ptr(key), CStr::from_ptr(value)) };
+///     PAIRS.lock().unwrap().push(format!("{}={}", key.to_str().unwrap(), value.to_str().unwrap()));
+/// }
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+///     if unsafe { (*record).kv_count } > 0 {
+///         assert!(ffi_logger_current_key_values(Some(pair), ptr::null_mut()));
+///     }
+///     0
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Record(sink), None) };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!("no pairs");
+/// log::info!(user = "alice", id = 7; "signed in");
+/// assert_eq!(*PAIRS.lock().unwrap(), ["user=alice", "id=7"]);
+/// ```
+///
+/// [FfiLogRecord::kv_count]: crate::FfiLogRecord::kv_count
+#[cfg(all(feature = "kv", feature = "std"))]
+#[no_mangle]
+pub extern "C" fn ffi_logger_current_key_values(
+    callback: Option<crate::KvCallback>,
+    data: *mut c_void,
+) -> bool {
+    callback.is_some_and(|callback| crate::kv::forward_current(callback, NonNull::new(data)))
+}
+
+/// Set [LogHandle::set_target_allowlist] of the logger registered by [FfiLogger::init] from an
+/// array of null-terminated targets.
+///
+/// Returns `false` and leaves the allowlist unchanged if there is no such logger, or if a target
+/// is not valid UTF-8. A `count` of zero clears the allowlist, in which case `targets` may be
+/// null.
+///
+/// ```
+/// use std::ffi::{c_char, c_void};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::ffi::ffi_logger_set_target_allowlist;
+/// use ffi_logger::FfiLogger;
+/// use log::{Level, Metadata};
+///
+/// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+/// unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+///
+/// let targets = [c"app".as_ptr()];
+/// assert!(unsafe { ffi_logger_set_target_allowlist(targets.as_ptr(), targets.len()) });
+///
+/// let info = |target| Metadata::builder().level(Level::Info).target(target).build();
+/// assert!(log::logger().enabled(&info("app::db")));
+/// assert!(!log::logger().enabled(&info("hyper")));
+/// ```
+///
+/// # Safety
+/// `targets` must point to `count` valid null-terminated strings, unless `count` is zero.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_set_target_allowlist(
+    targets: *const *const c_char,
+    count: usize,
+) -> bool {
+    set_target_list(targets, count, LogHandle::set_target_allowlist)
+}
+
+/// Set [LogHandle::set_target_denylist] of the logger registered by [FfiLogger::init] from an
+/// array of null-terminated targets.
+///
+/// This behaves the same as [ffi_logger_set_target_allowlist] otherwise.
+///
+/// # Safety
+/// `targets` must point to `count` valid null-terminated strings, unless `count` is zero.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_set_target_denylist(
+    targets: *const *const c_char,
+    count: usize,
+) -> bool {
+    set_target_list(targets, count, LogHandle::set_target_denylist)
+}
+
+/// Set the levels of the logger registered by [FfiLogger::init] from `env_logger` style
+/// directives, as with [LogHandle::set_filter].
+///
+/// Returns `false` and changes nothing if there is no such logger, or the directives are not
+/// valid UTF-8 or contain an invalid directive.
+///
+/// ```
+/// use ffi_logger::ffi::ffi_logger_set_filter;
+/// # use std::ffi::{c_char, c_void};
+/// # use std::ptr::NonNull;
+/// # use ffi_logger::FfiLogger;
+/// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+/// # unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+///
+/// unsafe {
+///     assert!(ffi_logger_set_filter(c"warn,app::net=trace".as_ptr()));
+///     assert!(!ffi_logger_set_filter(c"warn,app::net=verbose".as_ptr()));
+/// }
+/// assert_eq!(log::max_level(), log::LevelFilter::Trace);
+/// ```
+///
+/// # Safety
+/// `spec` must be a valid null-terminated string.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_set_filter(spec: *const c_char) -> bool {
+    let Some(handle) = LogHandle::registered() else {
+        return false;
+    };
+
+    CStr::from_ptr(spec)
+        .to_str()
+        .is_ok_and(|spec| handle.set_filter(spec))
+}
+
+/// Free a message passed to an [OwnedCallback](crate::OwnedCallback). Nothing is done if the
+/// message is null.
+///
+/// # Safety
+/// The message must have been passed to an [OwnedCallback](crate::OwnedCallback), and not
+/// have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_free_message(message: *mut c_char) {
+    if !message.is_null() {
+        drop(CString::from_raw(message));
+    }
+}
+
+/// A [LogHandle] to the logger registered by [FfiLogger::init], which a C host can keep instead
+/// of looking the logger up for every call.
+///
+/// This is only ever used through a pointer made by [ffi_logger_handle_new], and freed by
+/// [ffi_logger_handle_free]. Every other function taking it may be called from any thread, and
+/// from several threads at once, as long as the handle has not been freed. The handle stays
+/// valid after [ffi_logger_handle_deinit], which only stops delivering to the callback.
+///
+/// ```
+/// use std::ffi::{c_char, c_void};
+/// use std::ptr::{self, NonNull};
+///
+/// use ffi_logger::ffi::*;
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+///     0
+/// }
+///
+/// assert!(ffi_logger_handle_new().is_null());
+///
+/// let mut data = 0u8;
+/// let status = unsafe { ffi_logger_init(Some(sink), ptr::from_mut(&mut data).cast(), 5) };
+/// assert_eq!(status, FfiInitStatus::Ok);
+///
+/// let handle = ffi_logger_handle_new();
+/// unsafe {
+///     assert!(ffi_logger_handle_set_level(handle, 2));
+///     assert!(!ffi_logger_handle_set_level(handle, 6));
+///     assert_eq!(ffi_logger_handle_max_level(handle), 2);
+///     assert!(ffi_logger_handle_is_active(handle));
+///
+///     assert_eq!(ffi_logger_handle_deinit(handle), ptr::from_mut(&mut data).cast());
+///     assert!(ffi_logger_handle_deinit(handle).is_null());
+///     assert!(!ffi_logger_handle_is_active(handle));
+///     ffi_logger_handle_free(handle);
+/// }
+/// ```
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[derive(Debug)]
+pub struct FfiLogHandle(LogHandle);
+
+/// Make a handle to the logger registered by [FfiLogger::init], to be freed with
+/// [ffi_logger_handle_free].
+///
+/// Returns null if there is no such logger.
+///
+/// [FfiLogger::init]: crate::FfiLogger::init
+#[no_mangle]
+pub extern "C" fn ffi_logger_handle_new() -> *mut FfiLogHandle {
+    LogHandle::registered().map_or(ptr::null_mut(), |handle| {
+        Box::into_raw(Box::new(FfiLogHandle(handle)))
+    })
+}
+
+/// Free a handle made by [ffi_logger_handle_new]. Nothing is done if the handle is null.
+///
+/// This only frees the handle, and the logger keeps delivering messages.
+///
+/// # Safety
+/// The handle must have been made by [ffi_logger_handle_new] and not have been freed already,
+/// and no other function may be using it on another thread.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_handle_free(handle: *mut FfiLogHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Stop delivering to the callback of the logger with [LogHandle::deinit], returning its user
+/// data, or null if it was already returned.
+///
+/// # Safety
+/// The handle must be valid, as described by [FfiLogHandle].
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_handle_deinit(handle: *const FfiLogHandle) -> *mut c_void {
+    (*handle)
+        .0
+        .deinit()
+        .map_or(ptr::null_mut(), NonNull::as_ptr)
+}
+
+/// Flush the logger with [LogHandle::flush].
+///
+/// # Safety
+/// The handle must be valid, as described by [FfiLogHandle].
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_handle_flush(handle: *const FfiLogHandle) {
+    (*handle).0.flush();
+}
+
+/// Set [LogHandle::set_level] of the logger from the integer level, returning whether it was
+/// valid.
+///
+/// An invalid level leaves the level unchanged.
+///
+/// # Safety
+/// The handle must be valid, as described by [FfiLogHandle].
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_handle_set_level(
+    handle: *const FfiLogHandle,
+    level: u8,
+) -> bool {
+    match level_filter(level) {
+        Some(level) => {
+            (*handle).0.set_level(level);
+            true
+        }
+        None => false,
+    }
+}
+
+/// [LogHandle::max_level] of the logger as an integer level.
+///
+/// # Safety
+/// The handle must be valid, as described by [FfiLogHandle].
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_handle_max_level(handle: *const FfiLogHandle) -> u8 {
+    filter_to_u8((*handle).0.max_level())
+}
+
+/// Mute or unmute the logger, as with [LogHandle::set_enabled].
+///
+/// # Safety
+/// The handle must be valid, as described by [FfiLogHandle].
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_handle_set_enabled(handle: *const FfiLogHandle, enabled: bool) {
+    (*handle).0.set_enabled(enabled);
+}
+
+/// Set [LogHandle::set_level_mask] of the logger.
+///
+/// # Safety
+/// The handle must be valid, as described by [FfiLogHandle].
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_handle_set_level_mask(handle: *const FfiLogHandle, mask: u8) {
+    (*handle).0.set_level_mask(mask);
+}
+
+/// Whether the logger has anywhere to deliver messages to, as with [LogHandle::is_active].
+///
+/// # Safety
+/// The handle must be valid, as described by [FfiLogHandle].
+#[no_mangle]
+pub unsafe extern "C" fn ffi_logger_handle_is_active(handle: *const FfiLogHandle) -> bool {
+    (*handle).0.is_active()
+}
+
+/// Convert the C strings to targets and pass them to the setter of the registered logger.
+///
+/// # Safety
+/// `targets` must point to `count` valid null-terminated strings, unless `count` is zero.
+unsafe fn set_target_list(
+    targets: *const *const c_char,
+    count: usize,
+    set: fn(&LogHandle, &[&str]),
+) -> bool {
+    let Some(handle) = LogHandle::registered() else {
+        return false;
+    };
+    let targets = match count {
+        0 => &[],
+        count => slice::from_raw_parts(targets, count),
+    };
+
+    let targets: Option<Vec<&str>> = targets
+        .iter()
+        .map(|&target| CStr::from_ptr(target).to_str().ok())
+        .collect();
+    match targets {
+        Some(targets) => {
+            set(&handle, &targets);
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,172 @@
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use log::LevelFilter;
+
+use crate::sync::RwLock;
+
+/// Per-target overrides of the logger's level filter.
+#[derive(Debug, Default)]
+pub(crate) struct TargetLevels {
+    levels: RwLock<Vec<(String, LevelFilter)>>,
+}
+
+impl TargetLevels {
+    /// Set the level for the target, and every target nested under it, replacing any previous
+    /// level set for exactly that target.
+    pub(crate) fn set(&self, target: &str, level: LevelFilter) {
+        let mut levels = self.levels.write();
+
+        match levels.iter_mut().find(|(prefix, _)| prefix == target) {
+            Some((_, old)) => *old = level,
+            None => levels.push((target.to_owned(), level)),
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        self.levels.write().clear();
+    }
+
+    /// Every target given a level, and its level.
+    pub(crate) fn all(&self) -> Vec<(String, LevelFilter)> {
+        self.levels.read().clone()
+    }
+
+    /// Replace every level with these, all at once.
+    pub(crate) fn replace(&self, levels: &[(&str, LevelFilter)]) {
+        *self.levels.write() = levels
+            .iter()
+            .map(|&(target, level)| (target.to_owned(), level))
+            .collect();
+    }
+
+    /// The level set for the longest prefix of the target, if there is one.
+    pub(crate) fn get(&self, target: &str) -> Option<LevelFilter> {
+        let levels = self.levels.read();
+
+        levels
+            .iter()
+            .filter(|(prefix, _)| is_within(target, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+    }
+}
+
+/// Targets that are muted, or the only ones passed on.
+#[derive(Debug, Default)]
+pub(crate) struct TargetLists {
+    lists: RwLock<Lists>,
+}
+
+#[derive(Debug, Default)]
+struct Lists {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl TargetLists {
+    pub(crate) fn set_allowed(&self, targets: &[&str]) {
+        self.lists.write().allow = targets.iter().map(|&target| target.to_owned()).collect();
+    }
+
+    pub(crate) fn set_denied(&self, targets: &[&str]) {
+        self.lists.write().deny = targets.iter().map(|&target| target.to_owned()).collect();
+    }
+
+    /// The allowed and the denied targets.
+    pub(crate) fn all(&self) -> (Vec<String>, Vec<String>) {
+        let lists = self.lists.read();
+        (lists.allow.clone(), lists.deny.clone())
+    }
+
+    /// Whether records of the target may be passed on.
+    ///
+    /// When any targets are allowed only those are, regardless of the denied targets.
+    pub(crate) fn allows(&self, target: &str) -> bool {
+        let lists = self.lists.read();
+        let within = |prefixes: &[String]| prefixes.iter().any(|prefix| is_within(target, prefix));
+
+        if lists.allow.is_empty() {
+            !within(&lists.deny)
+        } else {
+            within(&lists.allow)
+        }
+    }
+}
+
+/// Filter directives in the simple forms of `RUST_LOG`: a comma-separated list of levels,
+/// `target=level` pairs, and targets on their own, which allow every level.
+#[derive(Debug, Default)]
+pub(crate) struct Directives<'a> {
+    /// The level of a directive without a target, if there was one.
+    pub(crate) level: Option<LevelFilter>,
+    pub(crate) targets: Vec<(&'a str, LevelFilter)>,
+}
+
+impl Directives<'_> {
+    /// Parse the directives, ignoring any that are invalid and the `/regex` filter of
+    /// `env_logger`.
+    #[cfg(feature = "std")]
+    pub(crate) fn parse(spec: &str) -> Directives<'_> {
+        let spec = spec.split('/').next().unwrap_or_default();
+        Directives::parse_with(spec, false).unwrap_or_default()
+    }
+
+    /// Parse the directives, or return [None] if any of them is invalid, including a `/regex`
+    /// filter.
+    pub(crate) fn parse_strict(spec: &str) -> Option<Directives<'_>> {
+        Directives::parse_with(spec, true)
+    }
+
+    fn parse_with(spec: &str, strict: bool) -> Option<Directives<'_>> {
+        let mut directives = Directives::default();
+
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => match (target.trim(), level.trim().parse()) {
+                    (target, Ok(level)) if !target.is_empty() => {
+                        directives.targets.push((target, level));
+                    }
+                    _ if strict => return None,
+                    _ => {}
+                },
+                None => match directive.parse() {
+                    Ok(level) => directives.level = Some(level),
+                    Err(_) if strict && !is_target(directive) => return None,
+                    Err(_) => directives.targets.push((directive, LevelFilter::Trace)),
+                },
+            }
+        }
+        Some(directives)
+    }
+
+    /// The most verbose level any of the directives allows, or `default` if there is no
+    /// directive without a target.
+    pub(crate) fn max_level(&self, default: LevelFilter) -> LevelFilter {
+        let level = self.level.unwrap_or(default);
+        self.targets
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(level, Ord::max)
+    }
+}
+
+/// Whether the directive could be a target, which is a path of identifiers such as
+/// `app::net`.
+fn is_target(directive: &str) -> bool {
+    directive.split("::").all(|segment| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    })
+}
+
+/// Whether the target is the prefix itself or a path nested under it, so that `hyper` covers
+/// `hyper::client` but not `hyperlocal`.
+fn is_within(target: &str, prefix: &str) -> bool {
+    target
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
@@ -0,0 +1,94 @@
+use alloc::vec::Vec;
+use core::ffi::{c_void, CStr};
+use core::ptr::NonNull;
+
+use log::Record;
+
+use crate::buffer::{self, InteriorNul};
+use crate::dispatch::{Header, RecordStrings};
+use crate::FormatCallback;
+
+/// A formatter set with [FfiLoggerBuilder::formatter](crate::FfiLoggerBuilder::formatter),
+/// along with its own user data.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Formatter {
+    pub(crate) format: FormatCallback,
+    pub(crate) data: Option<NonNull<c_void>>,
+}
+
+// Safety: The formatter & data are required to be thread-safe by the builder.
+unsafe impl Send for Formatter {}
+unsafe impl Sync for Formatter {}
+
+/// What was cut off when writing a message with a [Formatter].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Formatted {
+    /// Whether the message passed to the formatter was cut off.
+    pub(crate) message_cut: bool,
+    /// Whether the output of the formatter was cut off at the room of a fixed buffer.
+    pub(crate) output_cut: bool,
+}
+
+impl Formatter {
+    /// Write the message for the record into the end of the buffer, returning what was cut off.
+    /// Returns [None] and leaves the buffer as it was if the formatter failed.
+    ///
+    /// The message passed to the formatter is cut off at the ceiling. With the `room` of a fixed
+    /// buffer, both it and the output are cut off at that as well, and the formatter is never
+    /// given a larger buffer.
+    pub(crate) fn write(
+        &self,
+        buffer: &mut Vec<u8>,
+        record: &Record,
+        header: &Header,
+        ceiling: usize,
+        room: Option<usize>,
+        interior_nul: InteriorNul,
+    ) -> Option<Formatted> {
+        // The message is formatted into the buffer first, and the formatter's output goes after
+        // it until it is moved into its place.
+        let start = buffer.len();
+        let limit = room.map_or(ceiling, |room| room.min(ceiling));
+        let message_cut = buffer::write_limited(buffer, start, *record.args(), Some(limit));
+        buffer::sanitize(buffer, start, interior_nul);
+        buffer.push(0);
+        let output = buffer.len();
+
+        let strings = RecordStrings::new(record);
+
+        // The formatter is called again with a larger buffer if the message did not fit.
+        for _ in 0..2 {
+            // Safety: Interior null bytes were handled above, and the message was terminated.
+            let message = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[start..output]) };
+            // The record is made again each time, as growing the buffer moves the message.
+            let record = strings.record(header, message);
+
+            let free = buffer.capacity() - output;
+            let capacity = room.map_or(free, |room| room.min(free));
+            let spare = buffer.as_mut_ptr().wrapping_add(output);
+            let written = (self.format)(self.data, &record, spare, capacity);
+            let Ok(written) = usize::try_from(written) else {
+                break;
+            };
+
+            // A fixed buffer is not grown, so the output is cut off at what fit, as with
+            // `snprintf`.
+            let output_cut = room.is_some() && written > capacity;
+            if written <= capacity || output_cut {
+                let written = written.min(capacity);
+                // Safety: The formatter initialized that many bytes.
+                unsafe { buffer.set_len(output + written) };
+                buffer.copy_within(output.., start);
+                buffer.truncate(start + written);
+                return Some(Formatted {
+                    message_cut,
+                    output_cut,
+                });
+            }
+            buffer.reserve(written);
+        }
+
+        buffer.truncate(start);
+        None
+    }
+}
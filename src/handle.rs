@@ -0,0 +1,1229 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ffi::{c_void, CStr};
+use core::fmt;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::time::Duration;
+
+use log::{Level, LevelFilter, Log};
+
+use crate::filter::Directives;
+use crate::level::filter_to_u8;
+use crate::recent::Recent;
+use crate::route::Route;
+use crate::{level_bit, thread, FfiCallback, FfiLogger, FfiLoggerConfig, SinkId, ALL_LEVELS};
+
+/// The logger registered by [FfiLogger::init], or null if there is none.
+static REGISTERED: AtomicPtr<FfiLogger> = AtomicPtr::new(ptr::null_mut());
+
+/// A handle to an [FfiLogger], used to inspect and control it at runtime.
+///
+/// The logger is usually the one registered with [log], but does not have to be. A handle made
+/// with [LogHandle::shared] keeps its logger alive, so that several independent loggers can be
+/// used without leaking them, such as one for each instance of a library.
+///
+/// ```
+/// use std::ffi::{c_char, c_void};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiLogger, LogHandle};
+///
+/// extern "C" fn busy(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+///     -16
+/// }
+///
+/// let logger: &'static FfiLogger = Box::leak(Box::new(unsafe { FfiLogger::new(busy, None) }));
+/// log::set_logger(logger).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// let handle = LogHandle::new(logger);
+/// assert_eq!(handle.last_error(), None);
+///
+/// log::info!("hello");
+/// assert_eq!(handle.last_error(), Some(-16));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogHandle {
+    logger: Logger,
+}
+
+/// The logger a [LogHandle] controls.
+#[derive(Debug, Clone)]
+enum Logger {
+    Static(&'static FfiLogger),
+    Shared(Arc<FfiLogger>),
+}
+
+impl Deref for Logger {
+    type Target = FfiLogger;
+
+    fn deref(&self) -> &FfiLogger {
+        match self {
+            Logger::Static(logger) => logger,
+            Logger::Shared(logger) => logger,
+        }
+    }
+}
+
+impl LogHandle {
+    /// Create a handle to the logger, which is usually the one given to [log::set_logger].
+    pub fn new(logger: &'static FfiLogger) -> LogHandle {
+        Self {
+            logger: Logger::Static(logger),
+        }
+    }
+
+    /// Create a handle that shares ownership of the logger, which does not need to be registered
+    /// with [log].
+    ///
+    /// Records can be passed to the logger through [LogHandle::logger], or directly through the
+    /// [Arc].
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::Arc;
+    ///
+    /// use ffi_logger::{FfiLogger, LogHandle};
+    /// use log::Log;
+    ///
+    /// extern "C" fn busy(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     -16
+    /// }
+    /// extern "C" fn idle(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     0
+    /// }
+    ///
+    /// let first = LogHandle::shared(Arc::new(unsafe { FfiLogger::new(busy, None) }));
+    /// let second = LogHandle::shared(Arc::new(unsafe { FfiLogger::new(idle, None) }));
+    ///
+    /// let record = log::Record::builder()
+    ///     .level(log::Level::Info)
+    ///     .args(format_args!("hello"))
+    ///     .build();
+    /// first.logger().log(&record);
+    /// second.logger().log(&record);
+    ///
+    /// assert_eq!(first.last_error(), Some(-16));
+    /// assert_eq!(second.last_error(), None);
+    /// ```
+    pub fn shared(logger: Arc<FfiLogger>) -> LogHandle {
+        Self {
+            logger: Logger::Shared(logger),
+        }
+    }
+
+    /// The logger this controls.
+    pub fn logger(&self) -> &FfiLogger {
+        &self.logger
+    }
+
+    /// Remember the logger as the one registered with [log], for the functions in
+    /// [crate::ffi].
+    pub(crate) fn register(logger: &'static FfiLogger) {
+        // Pairs with the acquire in `registered`, so the logger is fully visible there.
+        REGISTERED.store(ptr::from_ref(logger).cast_mut(), Ordering::Release);
+    }
+
+    /// A handle to the logger registered by [FfiLogger::init], if there is one.
+    pub(crate) fn registered() -> Option<LogHandle> {
+        // Safety: Only `&'static` loggers are stored.
+        let logger = unsafe { REGISTERED.load(Ordering::Acquire).as_ref() }?;
+        Some(Self::new(logger))
+    }
+
+    /// Point the logger at a different callback and user data, returning the previous user data.
+    ///
+    /// Both are replaced together, so the new callback is never passed the previous data.
+    /// However, calls that are already in progress on other threads may still be using the
+    /// previous callback and data when this returns, so they must stay valid until those are
+    /// known to have finished.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// extern "C" fn old(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     -1
+    /// }
+    ///
+    /// extern "C" fn new(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     0
+    /// }
+    ///
+    /// let mut file = 1u8;
+    /// let file = NonNull::from(&mut file).cast();
+    /// let handle = unsafe { FfiLogger::init(old, Some(file), log::LevelFilter::Info) }.unwrap();
+    ///
+    /// let previous = unsafe { handle.set_callback(FfiCallback::Message(new), None) };
+    /// assert_eq!(previous, Some(file));
+    ///
+    /// log::info!("delivered to the new callback");
+    /// assert_eq!(handle.last_error(), None);
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply to the new callback and data.
+    pub unsafe fn set_callback(
+        &self,
+        logger: FfiCallback,
+        data: Option<NonNull<c_void>>,
+    ) -> Option<NonNull<c_void>> {
+        let previous = self
+            .logger
+            .dispatcher
+            .routes
+            .update(|routes| routes.primary.replace(Route { logger, data }))
+            .and_then(|route| route.data);
+        self.logger.poison.set(false);
+        previous
+    }
+
+    /// Point the logger's callback at different user data, returning the previous user data.
+    ///
+    /// This can be done while other threads are logging, and each message is passed either the
+    /// previous or the new data, never a mix. As with [LogHandle::set_callback], calls already in
+    /// progress may still be using the previous data when this returns. Nothing is changed if
+    /// the logger has no callback because of [LogHandle::deinit].
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let (mut old, mut new) = (0u8, 1u8);
+    /// let old = NonNull::from(&mut old).cast();
+    /// let new = NonNull::from(&mut new).cast();
+    ///
+    /// let handle = unsafe { FfiLogger::init(sink, Some(old), log::LevelFilter::Info) }.unwrap();
+    /// assert_eq!(unsafe { handle.set_data(Some(new)) }, Some(old));
+    /// assert_eq!(handle.deinit(), Some(new));
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply to the new data.
+    pub unsafe fn set_data(&self, data: Option<NonNull<c_void>>) -> Option<NonNull<c_void>> {
+        self.logger.dispatcher.routes.update(|routes| {
+            let route = routes.primary.as_mut()?;
+            core::mem::replace(&mut route.data, data)
+        })
+    }
+
+    /// Set the second pointer passed to [ContextCallback](crate::ContextCallback) callbacks,
+    /// returning the previous one. It is null until this is called.
+    ///
+    /// This is shared by every such callback of the logger, including sinks. As with
+    /// [LogHandle::set_data], calls already in progress on other threads may still be passed the
+    /// previous context when this returns.
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply to the context as to the user data.
+    pub unsafe fn set_context_ptr(
+        &self,
+        context: Option<NonNull<c_void>>,
+    ) -> Option<NonNull<c_void>> {
+        self.logger.dispatcher.set_context(context)
+    }
+
+    /// Set the user data passed to the logger's callback for records logged on the current
+    /// thread, or remove it with [None], returning the previous data of the thread.
+    ///
+    /// This only has an effect if the logger was built with
+    /// [FfiLoggerBuilder::thread_data](crate::FfiLoggerBuilder::thread_data). Threads without
+    /// any data set are passed the logger's own data. The data of a thread is forgotten, but not
+    /// deallocated, when it exits.
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply to the data, which must stay valid until
+    /// it is replaced or removed, or the thread exits. With an
+    /// [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger it must stay valid until the
+    /// records logged with it are delivered, such as by flushing the logger before removing it.
+    #[cfg(feature = "std")]
+    pub unsafe fn set_thread_data(&self, data: Option<NonNull<c_void>>) -> Option<NonNull<c_void>> {
+        thread::set_data(self.logger.thread_key(), data)
+    }
+
+    /// Stop delivering to the logger's callback, returning its user data so that it can be
+    /// deallocated.
+    ///
+    /// The data is only returned by the first call, and later calls return [None], so the
+    /// ownership of it is only handed back once even if shutdown code runs twice. Sinks added
+    /// with [LogHandle::add_sink] are not affected, and must be removed separately. For an
+    /// [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger, the queued records are
+    /// delivered and the worker thread is stopped first, waiting for at most the
+    /// [deinit_timeout](crate::FfiLoggerBuilder::deinit_timeout).
+    ///
+    /// As with [LogHandle::set_callback], calls already in progress on other threads may still be
+    /// using the callback and data when this returns. Setting [log::set_max_level] to
+    /// [LevelFilter::Off](log::LevelFilter::Off) first and waiting for those calls to finish
+    /// avoids this.
+    ///
+    /// With a [shutdown notification](crate::FfiLoggerBuilder::shutdown_notification) the callback
+    /// is called once more with a null message before the data is returned. Data owned by a
+    /// logger made with [FfiLogger::with_owned_data] is dropped instead, and [None] is returned.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let data = NonNull::from(Box::leak(Box::new(0u8))).cast();
+    /// let handle = unsafe { FfiLogger::init(sink, Some(data), log::LevelFilter::Info) }.unwrap();
+    ///
+    /// assert_eq!(handle.deinit(), Some(data));
+    /// assert_eq!(handle.deinit(), None);
+    /// # drop(unsafe { Box::from_raw(data.cast::<u8>().as_ptr()) });
+    /// ```
+    pub fn deinit(&self) -> Option<NonNull<c_void>> {
+        #[cfg(feature = "std")]
+        if let Some(worker) = &self.logger.worker {
+            worker.stop(Some(worker.deinit_timeout));
+        }
+        self.logger.dispatcher.deliver_batch();
+        self.logger.poison.set(true);
+
+        let route = self
+            .logger
+            .dispatcher
+            .routes
+            .update(|routes| routes.primary.take())?;
+        if self.logger.shutdown_notification {
+            route.shut_down(self.logger.dispatcher.context());
+        }
+
+        let mut owned = self.logger.owned.lock();
+        if owned
+            .as_ref()
+            .is_some_and(|owned| route.data == Some(owned.data()))
+        {
+            *owned = None;
+            return None;
+        }
+        route.data
+    }
+
+    /// Give the logger a callback and user data again after [LogHandle::deinit].
+    ///
+    /// This lets logging be brought back up without registering a new logger, which [log]
+    /// does not allow, such as when a plugin's logging sink is torn down and rebuilt. Returns
+    /// `false` and leaves the logger unchanged if it still has a callback, in which case
+    /// [LogHandle::set_callback] can be used to replace it instead.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     0
+    /// }
+    ///
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    /// assert!(!unsafe { handle.reinit(FfiCallback::Message(sink), None) });
+    ///
+    /// handle.deinit();
+    /// assert!(unsafe { handle.reinit(FfiCallback::Message(sink), None) });
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply to the callback and data.
+    ///
+    /// No logging may happen between [LogHandle::deinit] and this call, as calls still in
+    /// progress from before the deinit could otherwise be using the previous data once it has
+    /// been deallocated.
+    pub unsafe fn reinit(&self, logger: FfiCallback, data: Option<NonNull<c_void>>) -> bool {
+        self.logger.dispatcher.routes.update(|routes| {
+            if routes.primary.is_some() {
+                return false;
+            }
+
+            routes.primary = Some(Route { logger, data });
+            self.logger.poison.set(false);
+            true
+        })
+    }
+
+    /// Whether the logger has anywhere to deliver messages to, which it does not after
+    /// [LogHandle::deinit] unless sinks were added with [LogHandle::add_sink].
+    ///
+    /// This is a single atomic load, so it can be checked before doing work that only matters
+    /// when the logs are consumed.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    /// assert!(handle.is_active());
+    ///
+    /// handle.deinit();
+    /// assert!(!handle.is_active());
+    /// ```
+    pub fn is_active(&self) -> bool {
+        self.logger.dispatcher.routes.is_active()
+    }
+
+    /// Make a guard that calls [LogHandle::deinit] when dropped, and passes the user data that
+    /// was handed back to `reclaim` so that it can be deallocated.
+    ///
+    /// Holding the guard for as long as logging is needed makes it hard to deallocate the data
+    /// while it may still be used, such as when returning early during shutdown.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let file = NonNull::from(Box::leak(Box::new(0u8))).cast();
+    /// let guard = unsafe { FfiLogger::init(sink, Some(file), log::LevelFilter::Info) }
+    ///     .unwrap()
+    ///     .into_guard(|data| {
+    ///         let data = data.unwrap().cast::<u8>();
+    ///         drop(unsafe { Box::from_raw(data.as_ptr()) });
+    ///     });
+    ///
+    /// log::info!("while the guard is held");
+    /// assert_eq!(guard.last_error(), None);
+    /// drop(guard);
+    /// ```
+    pub fn into_guard(self, reclaim: impl FnOnce(Option<NonNull<c_void>>) + 'static) -> LogGuard {
+        LogGuard {
+            handle: self,
+            reclaim: Some(Box::new(reclaim)),
+        }
+    }
+
+    /// Also deliver every message to another callback with its own user data, in addition to
+    /// the callback the logger was created with.
+    ///
+    /// Callbacks are called one after another in the order they were added, after the
+    /// logger's own callback. Each one is called even if an earlier one returned an error.
+    /// Sinks are only delivered to when the logger is used as a [log::Log] implementation.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// static CALLS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// extern "C" fn failing(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     CALLS.fetch_add(1, Ordering::Relaxed);
+    ///     -1
+    /// }
+    ///
+    /// let handle = unsafe { FfiLogger::init(failing, None, log::LevelFilter::Info) }.unwrap();
+    /// let sink = unsafe { handle.add_sink(FfiCallback::Message(failing), None) };
+    ///
+    /// log::info!("to both");
+    /// assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+    ///
+    /// handle.remove_sink(sink);
+    /// log::info!("to one");
+    /// assert_eq!(CALLS.load(Ordering::Relaxed), 3);
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply to the callback and data.
+    pub unsafe fn add_sink(&self, logger: FfiCallback, data: Option<NonNull<c_void>>) -> SinkId {
+        self.logger
+            .dispatcher
+            .routes
+            .update(|routes| routes.add(Route { logger, data }))
+    }
+
+    /// Stop delivering to a sink added with [LogHandle::add_sink], returning its user data.
+    ///
+    /// Returns [None] if the sink was already removed. As with [LogHandle::set_callback], calls
+    /// already in progress on other threads may still be using the sink.
+    pub fn remove_sink(&self, sink: SinkId) -> Option<Option<NonNull<c_void>>> {
+        self.logger
+            .dispatcher
+            .routes
+            .update(|routes| routes.remove(sink))
+            .map(|route| route.data)
+    }
+
+    /// Set the most verbose level the logger passes on to the callback.
+    ///
+    /// This is applied on top of [log::max_level], so records must pass both. By default every
+    /// level is passed on. Targets given a level with [LogHandle::set_target_level] use that
+    /// level instead.
+    pub fn set_level(&self, level: LevelFilter) {
+        self.logger
+            .level
+            .store(filter_to_u8(level), Ordering::Relaxed);
+    }
+
+    /// The most verbose level the logger passes on to the callback.
+    pub fn level(&self) -> LevelFilter {
+        self.logger.level()
+    }
+
+    /// The most verbose level that can currently reach the callback, combining
+    /// [log::max_level] with [LogHandle::level].
+    ///
+    /// Levels set for specific targets with [LogHandle::set_target_level] are not taken into
+    /// account, beyond still being limited by [log::max_level].
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// use log::LevelFilter;
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let handle = unsafe { FfiLogger::init(sink, None, LevelFilter::Debug) }.unwrap();
+    /// handle.set_level(LevelFilter::Info);
+    /// assert_eq!(handle.max_level(), LevelFilter::Info);
+    ///
+    /// log::set_max_level(LevelFilter::Warn);
+    /// assert_eq!(handle.max_level(), LevelFilter::Warn);
+    /// ```
+    pub fn max_level(&self) -> LevelFilter {
+        log::max_level().min(self.level())
+    }
+
+    /// Mute or unmute the logger, such as during a section where logging would cost too much.
+    ///
+    /// While muted, records are neither formatted nor passed to any callback, and are not
+    /// counted anywhere. The only exception is the
+    /// [audit callback](crate::FfiLoggerBuilder::audit_callback), which is still passed every
+    /// record.
+    /// Unlike [LogHandle::deinit] this is undone by unmuting, and is only a single atomic store.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::{FfiLogger, LogHandle};
+    /// use log::{Level, Log, Metadata};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(unsafe { FfiLogger::new(sink, None) }));
+    /// let handle = LogHandle::new(logger);
+    /// let info = Metadata::builder().level(Level::Info).build();
+    ///
+    /// handle.set_enabled(false);
+    /// assert!(!logger.enabled(&info));
+    ///
+    /// handle.set_enabled(true);
+    /// assert!(logger.enabled(&info));
+    /// ```
+    pub fn set_enabled(&self, enabled: bool) {
+        self.logger.on.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Write messages to stderr while the logger has no callback, as with
+    /// [FfiLoggerBuilder::stderr_fallback](crate::FfiLoggerBuilder::stderr_fallback).
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    /// handle.set_stderr_fallback(true);
+    /// handle.deinit();
+    ///
+    /// // Written to stderr along with its level and target.
+    /// log::info!("still seen");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn set_stderr_fallback(&self, enabled: bool) {
+        self.logger
+            .stderr_fallback
+            .store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether the logger is unmuted, as set by [LogHandle::set_enabled].
+    pub fn is_enabled(&self) -> bool {
+        self.logger.on.load(Ordering::Relaxed)
+    }
+
+    /// Only pass on the levels in the mask, in which bit 0 is [Error](log::Level::Error), bit 1 is
+    /// [Warn](log::Level::Warn), and so on up to bit 4 for [Trace](log::Level::Trace).
+    ///
+    /// This allows a set of levels that a single threshold cannot express. Records must pass
+    /// both the mask and the level filters. Higher bits are ignored, and by default every level
+    /// is passed on.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::{FfiLogger, LogHandle};
+    /// use log::{Level, Log, Metadata};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(unsafe { FfiLogger::new(sink, None) }));
+    /// let handle = LogHandle::new(logger);
+    /// let enabled = |level| logger.enabled(&Metadata::builder().level(level).build());
+    ///
+    /// handle.set_level_mask(0b1_0001);
+    /// assert!(enabled(Level::Error));
+    /// assert!(!enabled(Level::Info));
+    /// assert!(enabled(Level::Trace));
+    /// ```
+    pub fn set_level_mask(&self, mask: u8) {
+        self.logger
+            .level_mask
+            .store(mask & ALL_LEVELS, Ordering::Relaxed);
+    }
+
+    /// The levels that are passed on, as set by [LogHandle::set_level_mask].
+    pub fn level_mask(&self) -> u8 {
+        self.logger.level_mask.load(Ordering::Relaxed)
+    }
+
+    /// Set the most verbose level passed on for a target, overriding [LogHandle::set_level].
+    ///
+    /// This also applies to every target nested under it, so setting `hyper` also affects
+    /// `hyper::client`. When several prefixes match a target, the longest one wins.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::{FfiLogger, LogHandle};
+    /// use log::{Level, LevelFilter, Log, Metadata};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(unsafe { FfiLogger::new(sink, None) }));
+    /// let handle = LogHandle::new(logger);
+    /// handle.set_level(LevelFilter::Info);
+    /// handle.set_target_level("hyper", LevelFilter::Error);
+    ///
+    /// let warn = |target| Metadata::builder().level(Level::Warn).target(target).build();
+    /// assert!(!logger.enabled(&warn("hyper::client")));
+    /// assert!(logger.enabled(&warn("hyperlocal")));
+    /// ```
+    pub fn set_target_level(&self, target: &str, level: LevelFilter) {
+        self.logger.target_levels.set(target, level);
+    }
+
+    /// Remove every level set with [LogHandle::set_target_level].
+    pub fn clear_target_levels(&self) {
+        self.logger.target_levels.clear();
+    }
+
+    /// Set the levels from directives in the simple forms of `env_logger`, such as
+    /// `info,app::net=trace,other=off`, as [FfiLogger::init_from_env] does from `RUST_LOG`.
+    ///
+    /// The level without a target is set with [LogHandle::set_level], defaulting to
+    /// [Error](LevelFilter::Error), and the levels of the targets replace all of those set with
+    /// [LogHandle::set_target_level]. A target on its own allows every level. Unlike with
+    /// `RUST_LOG`, invalid directives and `/regex` filters are not ignored, and nothing is
+    /// changed and `false` is returned instead.
+    ///
+    /// For a logger registered with [FfiLogger::init] or [FfiLogger::init_from_env], which set
+    /// [log::set_max_level] themselves, it is also set to the most verbose of the levels. It is
+    /// left untouched for any other logger, such as one registered with
+    /// [FfiLogger::init_keep_max_level], so that it still applies on top of the levels.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::FfiLogger;
+    /// use log::{Level, LevelFilter, Log, Metadata};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let handle = unsafe { FfiLogger::init(sink, None, LevelFilter::Info) }.unwrap();
+    /// assert!(handle.set_filter("info,app::net=trace,other=off"));
+    ///
+    /// assert_eq!(handle.level(), LevelFilter::Info);
+    /// assert_eq!(log::max_level(), LevelFilter::Trace);
+    /// let info = |target| Metadata::builder().level(Level::Info).target(target).build();
+    /// assert!(handle.logger().enabled(&info("app")));
+    /// assert!(!handle.logger().enabled(&info("other::db")));
+    ///
+    /// assert!(!handle.set_filter("debug,app=loud"));
+    /// assert_eq!(handle.level(), LevelFilter::Info);
+    /// ```
+    ///
+    /// [FfiLogger::init_from_env]: crate::FfiLogger::init_from_env
+    pub fn set_filter(&self, spec: &str) -> bool {
+        let Some(directives) = Directives::parse_strict(spec) else {
+            return false;
+        };
+
+        self.set_level(directives.level.unwrap_or(LevelFilter::Error));
+        self.logger.target_levels.replace(&directives.targets);
+        if self.logger.sets_max_level.load(Ordering::Relaxed) {
+            log::set_max_level(directives.max_level(LevelFilter::Error));
+        }
+        true
+    }
+
+    /// Only pass on records of these targets, and the targets nested under them, replacing any
+    /// previous allowlist.
+    ///
+    /// While the allowlist is not empty it takes precedence over the denylist set with
+    /// [LogHandle::set_target_denylist]. An empty allowlist allows every target again. Records of
+    /// an allowed target must still pass the level filters.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::{FfiLogger, LogHandle};
+    /// use log::{Level, Log, Metadata};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(unsafe { FfiLogger::new(sink, None) }));
+    /// let handle = LogHandle::new(logger);
+    /// let info = |target| Metadata::builder().level(Level::Info).target(target).build();
+    ///
+    /// handle.set_target_denylist(&["hyper", "mio"]);
+    /// assert!(!logger.enabled(&info("hyper::client")));
+    /// assert!(logger.enabled(&info("app")));
+    ///
+    /// handle.set_target_allowlist(&["hyper::client"]);
+    /// assert!(logger.enabled(&info("hyper::client")));
+    /// assert!(!logger.enabled(&info("app")));
+    /// ```
+    pub fn set_target_allowlist(&self, targets: &[&str]) {
+        self.logger.target_lists.set_allowed(targets);
+    }
+
+    /// Never pass on records of these targets, or the targets nested under them, replacing any
+    /// previous denylist.
+    ///
+    /// This is ignored while there is an allowlist set with [LogHandle::set_target_allowlist]. An
+    /// empty denylist mutes no targets.
+    pub fn set_target_denylist(&self, targets: &[&str]) {
+        self.logger.target_lists.set_denied(targets);
+    }
+
+    /// Take the messages kept by
+    /// [FfiLoggerBuilder::recent_messages](crate::FfiLoggerBuilder::recent_messages), from oldest
+    /// to newest. This is empty if messages are not kept.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .recent_messages(2)
+    ///         .build()
+    /// };
+    /// let handle = logger.leak_and_register().unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// for i in 0..3 {
+    ///     log::info!("message {i}");
+    /// }
+    /// assert_eq!(handle.drain_recent(), ["message 1", "message 2"]);
+    /// assert!(handle.drain_recent().is_empty());
+    /// ```
+    pub fn drain_recent(&self) -> Vec<String> {
+        self.logger
+            .recent
+            .as_ref()
+            .map_or_else(Vec::new, Recent::drain)
+    }
+
+    /// Copy the kept messages into the buffer for [crate::ffi::ffi_logger_copy_recent].
+    pub(crate) fn copy_recent(&self, buffer: &mut [u8]) -> usize {
+        self.logger
+            .recent
+            .as_ref()
+            .map_or(0, |recent| recent.copy_to(buffer))
+    }
+
+    /// Write the context before the message of every record, followed by a colon and the
+    /// [separator](crate::FfiLoggerBuilder::separator), such as to tag every line with a session
+    /// id. An empty context clears it.
+    ///
+    /// With [FfiLoggerBuilder::json](crate::FfiLoggerBuilder::json) the context is written as a
+    /// separate `context` field instead.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::FfiLogger;
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"session 42: connected");
+    ///     0
+    /// }
+    ///
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    /// handle.set_context("session 42");
+    /// log::info!("connected");
+    /// ```
+    pub fn set_context(&self, context: &str) {
+        *self.logger.context.write() = (!context.is_empty()).then(|| Arc::from(context));
+    }
+
+    /// Save the settings of the logger that can be changed through a handle, to be restored
+    /// later with [LogHandle::apply].
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::{FfiLogger, LogHandle};
+    /// use log::LevelFilter;
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let new = || LogHandle::new(Box::leak(Box::new(unsafe { FfiLogger::new(sink, None) })));
+    ///
+    /// let before = new();
+    /// before.set_level(LevelFilter::Debug);
+    /// before.set_target_level("app::net", LevelFilter::Trace);
+    /// before.set_target_denylist(&["noisy"]);
+    /// before.set_context("plugin");
+    /// let config = before.snapshot();
+    ///
+    /// let after = new();
+    /// after.apply(&config);
+    /// assert_eq!(after.snapshot(), config);
+    /// assert_eq!(after.level(), LevelFilter::Debug);
+    /// ```
+    pub fn snapshot(&self) -> FfiLoggerConfig {
+        let (target_allowlist, target_denylist) = self.logger.target_lists.all();
+
+        FfiLoggerConfig {
+            level: self.level(),
+            enabled: self.is_enabled(),
+            level_mask: self.level_mask(),
+            target_levels: self.logger.target_levels.all(),
+            target_allowlist,
+            target_denylist,
+            context: self.logger.context.read().as_deref().map(String::from),
+            #[cfg(feature = "std")]
+            stderr_fallback: self.logger.stderr_fallback.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Restore the settings saved by [LogHandle::snapshot], replacing all of the current ones.
+    ///
+    /// Each setting is replaced on its own, so records logged on other threads meanwhile may
+    /// be filtered by a mix of the previous and the restored settings.
+    pub fn apply(&self, config: &FfiLoggerConfig) {
+        let target_levels: Vec<_> = config
+            .target_levels
+            .iter()
+            .map(|(target, level)| (target.as_str(), *level))
+            .collect();
+        fn as_strs(targets: &[String]) -> Vec<&str> {
+            targets.iter().map(String::as_str).collect()
+        }
+
+        self.set_level(config.level);
+        self.set_level_mask(config.level_mask);
+        self.logger.target_levels.replace(&target_levels);
+        self.set_target_allowlist(&as_strs(&config.target_allowlist));
+        self.set_target_denylist(&as_strs(&config.target_denylist));
+        self.set_context(config.context.as_deref().unwrap_or_default());
+        #[cfg(feature = "std")]
+        self.set_stderr_fallback(config.stderr_fallback);
+        self.set_enabled(config.enabled);
+    }
+
+    /// Deliver the bytes exactly as they are to every [RawCallback](crate::RawCallback), such as
+    /// to dump a binary frame into the log stream. Returns how many callbacks received them.
+    ///
+    /// Unlike records logged through [log], the bytes are not formatted, converted to a C
+    /// string, or passed to the other kinds of callbacks. They are still only delivered if the
+    /// level is enabled by [log::max_level], [LogHandle::level], [LogHandle::set_level_mask],
+    /// and [LogHandle::set_enabled], but target filters, rate limits and the recent messages do
+    /// not apply.
+    ///
+    /// ```
+    /// use std::ffi::c_void;
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger, LogHandle};
+    /// use log::Level;
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: FfiLevel, bytes: *const u8, len: usize) -> isize {
+    ///     let bytes = unsafe { std::slice::from_raw_parts(bytes, len) };
+    ///     assert_eq!(bytes, b"\0\xFF\0frame");
+    ///     len as isize
+    /// }
+    ///
+    /// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Raw(sink), None) };
+    /// let handle = LogHandle::new(Box::leak(Box::new(logger)));
+    /// log::set_max_level(log::LevelFilter::Debug);
+    ///
+    /// assert_eq!(handle.log_bytes(Level::Debug, b"\0\xFF\0frame"), 1);
+    /// assert_eq!(handle.log_bytes(Level::Trace, b"filtered"), 0);
+    /// ```
+    pub fn log_bytes(&self, level: Level, bytes: &[u8]) -> usize {
+        let logger = self.logger();
+        logger.poison.check();
+        if !logger.on.load(Ordering::Relaxed)
+            || level > log::max_level()
+            || level > logger.level()
+            || logger.level_mask.load(Ordering::Relaxed) & level_bit(level) == 0
+        {
+            return 0;
+        }
+        let Some(_entered) = thread::enter() else {
+            logger
+                .dispatcher
+                .drop_message(level, c"logged from within the logger");
+            return 0;
+        };
+
+        logger.dispatcher.dispatch_raw(level, bytes)
+    }
+
+    /// Deliver a message that needs no formatting from a signal handler, such as a crash
+    /// handler's last words. Returns how many callbacks received it.
+    ///
+    /// **This is the only method of the logger that is safe to call from a signal handler.** It
+    /// does not allocate, lock, format, or use thread-locals, and as such it skips everything
+    /// that would. The message is passed as it is to every [Callback](crate::Callback),
+    /// [ContextCallback](crate::ContextCallback), [IdCallback](crate::IdCallback),
+    /// [BufferCallback](crate::BufferCallback), [RawCallback](crate::RawCallback), and
+    /// [SyslogCallback](crate::SyslogCallback), but not to the other kinds of callbacks, nor to
+    /// the worker thread of an [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger. It
+    /// is only delivered if the level is enabled by [log::max_level], [LogHandle::level],
+    /// [LogHandle::set_level_mask], and [LogHandle::set_enabled]. The callbacks must themselves
+    /// be async-signal-safe for this to be, such as by only calling `write`.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::FfiLogger;
+    /// use log::Level;
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"caught SIGSEGV");
+    ///     0
+    /// }
+    ///
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    /// // From within the signal handler:
+    /// assert_eq!(handle.log_raw_signal_safe(Level::Error, c"caught SIGSEGV"), 1);
+    /// assert_eq!(handle.log_raw_signal_safe(Level::Debug, c"filtered"), 0);
+    /// ```
+    pub fn log_raw_signal_safe(&self, level: Level, message: &'static CStr) -> usize {
+        let logger = self.logger();
+        if !logger.on.load(Ordering::Relaxed)
+            || level > log::max_level()
+            || level > logger.level()
+            || logger.level_mask.load(Ordering::Relaxed) & level_bit(level) == 0
+        {
+            return 0;
+        }
+
+        logger.dispatcher.dispatch_static(level, message)
+    }
+
+    /// Flush the logger, calling the flush callback given to
+    /// [FfiLoggerBuilder::flush_callback](crate::FfiLoggerBuilder::flush_callback) if there is
+    /// one.
+    ///
+    /// Once this returns, every record logged before it was called has been passed to the
+    /// callbacks, and then the flush callback has returned. Records held back for a
+    /// [batch](crate::FfiLoggerBuilder::batch) or by
+    /// [deduplicate](crate::FfiLoggerBuilder::deduplicate) are delivered as well. For an
+    /// [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger this waits for the worker
+    /// thread to deliver everything queued, for as long as that takes, except on the worker
+    /// thread itself. Whatever the callbacks do with the messages afterwards is up to the flush
+    /// callback.
+    ///
+    /// The flush callback is skipped when no callback was called since the last flush, as there
+    /// is nothing new for it to flush. Use [LogHandle::force_flush] to call it regardless.
+    pub fn flush(&self) {
+        self.logger.flush();
+    }
+
+    /// Flush the logger like [LogHandle::flush], but always call the flush callback, even if
+    /// nothing was delivered since the last flush.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// static FLUSHES: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// extern "C" fn flush(_: Option<NonNull<c_void>>) {
+    ///     FLUSHES.fetch_add(1, Ordering::Relaxed);
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(sink))
+    ///         .flush_callback(flush)
+    ///         .build()
+    /// };
+    /// let handle = logger.leak_and_register().unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("written");
+    /// handle.flush();
+    /// handle.flush();
+    /// assert_eq!(FLUSHES.load(Ordering::Relaxed), 1);
+    ///
+    /// handle.force_flush();
+    /// assert_eq!(FLUSHES.load(Ordering::Relaxed), 2);
+    /// ```
+    pub fn force_flush(&self) {
+        self.logger.flush_within(None, true);
+    }
+
+    /// Flush the logger like [LogHandle::flush], but wait for at most the timeout for the worker
+    /// thread of an [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger, such as so
+    /// that a stuck sink cannot hang the caller.
+    ///
+    /// Returns `false` if that timed out, in which case the records still queued are delivered
+    /// later, and the flush callback is not called. Loggers without a worker thread never time
+    /// out, as they deliver each record before the call logging it returns.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::time::Duration;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle, Overflow};
+    ///
+    /// extern "C" fn slow(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     std::thread::sleep(Duration::from_millis(50));
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(slow))
+    ///         .asynchronous(16, Overflow::Block)
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// for i in 0..5 {
+    ///     log::info!("queued {i}");
+    /// }
+    ///
+    /// let handle = LogHandle::new(logger);
+    /// assert!(!handle.flush_timeout(Duration::from_millis(1)));
+    /// assert!(handle.flush_timeout(Duration::from_secs(60)));
+    /// # }
+    /// ```
+    pub fn flush_timeout(&self, timeout: Duration) -> bool {
+        self.logger.flush_within(Some(timeout), false)
+    }
+
+    /// Whether the most recent call of a callback returned success, which is `true` before any
+    /// callback was called.
+    ///
+    /// This is best-effort, as it is shared by every thread, sink and level callback of the
+    /// logger. It only tells whether a message just logged reached its sink when checked right
+    /// after logging it, with no other thread logging meanwhile, and when the message was not
+    /// filtered out, dropped, or queued for an
+    /// [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void, CStr};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     match unsafe { CStr::from_ptr(message) }.to_bytes() {
+    ///         b"disk full" => -28,
+    ///         _ => 0,
+    ///     }
+    /// }
+    ///
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    /// log::error!("disk full");
+    /// assert!(!handle.last_delivery_ok());
+    /// log::error!("disk freed");
+    /// assert!(handle.last_delivery_ok());
+    /// ```
+    pub fn last_delivery_ok(&self) -> bool {
+        self.logger.dispatcher.last_ok.load(Ordering::Relaxed)
+    }
+
+    /// The most recent negative error code returned by the callback, if any has been.
+    pub fn last_error(&self) -> Option<isize> {
+        match self.logger.dispatcher.last_error.load(Ordering::Relaxed) {
+            0 => None,
+            error => Some(error),
+        }
+    }
+
+    /// The number of times a callback returned an error, the last of which is
+    /// [LogHandle::last_error].
+    pub fn error_count(&self) -> usize {
+        self.logger.dispatcher.errors.load(Ordering::Relaxed)
+    }
+
+    /// The number of times the callback reported writing fewer bytes than the message held.
+    ///
+    /// Short writes are counted separately from errors. With
+    /// [FfiLoggerBuilder::retry_short_writes](crate::FfiLoggerBuilder::retry_short_writes) a
+    /// message only counts if the callback stopped writing before it was all written.
+    pub fn short_writes(&self) -> usize {
+        self.logger.dispatcher.short_writes.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages that were cut off for being longer than the limit set with
+    /// [FfiLoggerBuilder::max_message_len](crate::FfiLoggerBuilder::max_message_len).
+    pub fn truncated_count(&self) -> usize {
+        self.logger.dispatcher.truncated.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages cut off for reaching the
+    /// [message_ceiling](crate::FfiLoggerBuilder::message_ceiling).
+    pub fn capped_count(&self) -> usize {
+        self.logger.dispatcher.capped.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages that were dropped instead of delivered.
+    ///
+    /// A message is dropped when formatting it panics, such as from a faulty
+    /// [Display](std::fmt::Display) implementation, in which case the panic is not propagated to
+    /// the code that logged it. Messages are also dropped while backing off from a busy sink, as
+    /// set up with [FfiLoggerBuilder::backpressure](crate::FfiLoggerBuilder::backpressure), and
+    /// when the queue of an [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger is full,
+    /// as are messages logged on a thread while a callback is running on it. Each one is also
+    /// reported to the [error callback](crate::FfiLoggerBuilder::error_callback), if there is one.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// use std::fmt;
+    ///
+    /// struct Faulty;
+    ///
+    /// impl fmt::Display for Faulty {
+    ///     fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         panic!("oops")
+    ///     }
+    /// }
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    ///
+    /// log::info!("{}", Faulty);
+    /// assert_eq!(handle.dropped_count(), 1);
+    /// # }
+    /// ```
+    pub fn dropped_count(&self) -> usize {
+        self.logger.dispatcher.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of records dropped by
+    /// [FfiLoggerBuilder::sample](crate::FfiLoggerBuilder::sample).
+    pub fn sampled_count(&self) -> usize {
+        self.logger.sampling.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of records the logger filtered out, if it was set up to count them with
+    /// [FfiLoggerBuilder::rejected](crate::FfiLoggerBuilder::rejected), and zero otherwise.
+    pub fn rejected_count(&self) -> usize {
+        self.logger
+            .dispatcher
+            .rejected_count
+            .load(Ordering::Relaxed)
+    }
+
+    /// The number of records of each level that passed the filters and were delivered, from
+    /// [Error](log::Level::Error) to [Trace](log::Level::Trace).
+    ///
+    /// Records are counted once they are handed to the callbacks or to the queue of an
+    /// [asynchronous](crate::FfiLoggerBuilder::asynchronous) logger, whatever the callbacks
+    /// return.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    ///
+    /// log::error!("one");
+    /// log::info!("two");
+    /// log::info!("three");
+    /// log::debug!("filtered");
+    /// assert_eq!(handle.level_counts(), [1, 0, 2, 0, 0]);
+    /// ```
+    pub fn level_counts(&self) -> [u64; 5] {
+        let counts = &self.logger.level_counts;
+        core::array::from_fn(|i| counts[i].load(Ordering::Relaxed))
+    }
+
+    /// The number of records logged since [LogHandle::deinit], without a callback being given
+    /// again with [LogHandle::reinit] or [LogHandle::set_callback].
+    ///
+    /// Records the logger filters out are counted too, but not those filtered out by
+    /// [log::max_level] before reaching it. Once the callback's data may have been deallocated,
+    /// no Rust code may log at all, so this being anything but zero points to a lifecycle bug
+    /// that could otherwise go unnoticed. This is only counted in debug builds, so that release
+    /// builds pay nothing for it, and is always zero in release builds.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// # use ffi_logger::FfiLogger;
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+    /// log::info!("shutting down");
+    ///
+    /// log::set_max_level(log::LevelFilter::Off);
+    /// handle.deinit();
+    /// // The rest of the shutdown, which must not log.
+    /// debug_assert_eq!(handle.logged_after_deinit(), 0);
+    /// ```
+    pub fn logged_after_deinit(&self) -> usize {
+        self.logger.poison.uses()
+    }
+}
+
+/// Calls [LogHandle::deinit] when dropped, passing the user data that was handed back to a
+/// closure. Made with [LogHandle::into_guard].
+///
+/// This dereferences to the [LogHandle], so that the logger can still be controlled while the
+/// guard is held.
+#[must_use = "the logger is deinitialized when the guard is dropped"]
+pub struct LogGuard {
+    handle: LogHandle,
+    reclaim: Option<Reclaim>,
+}
+
+/// Passed the user data once the logger is deinitialized.
+type Reclaim = Box<dyn FnOnce(Option<NonNull<c_void>>)>;
+
+impl Deref for LogGuard {
+    type Target = LogHandle;
+
+    fn deref(&self) -> &LogHandle {
+        &self.handle
+    }
+}
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        let data = self.handle.deinit();
+        if let Some(reclaim) = self.reclaim.take() {
+            reclaim(data);
+        }
+    }
+}
+
+impl fmt::Debug for LogGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogGuard")
+            .field("handle", &self.handle)
+            .finish_non_exhaustive()
+    }
+}
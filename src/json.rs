@@ -0,0 +1,79 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write as _};
+
+use log::Record;
+
+use crate::buffer::{write_limited, Writer};
+use crate::{color, target};
+
+/// Write the record as a JSON object with `level`, `target`, `message`, and `timestamp` fields,
+/// and a `context` field if there is a context. The `level` field is the given name of the level.
+///
+/// With colors, the level is wrapped in the ANSI SGR sequences for its color. With a limit, the
+/// message field is cut off before escaping it, as with [write_limited]. Returns whether it was.
+pub(crate) fn write_record(
+    buffer: &mut Vec<u8>,
+    record: &Record,
+    level: &str,
+    timestamp: u64,
+    colors: bool,
+    context: Option<&str>,
+    limit: Option<usize>,
+) -> bool {
+    buffer.extend_from_slice(b"{\"level\":\"");
+    if colors {
+        escape(buffer, color::sgr(record.level()));
+        escape(buffer, level);
+        escape(buffer, color::RESET);
+    } else {
+        escape(buffer, level);
+    }
+    buffer.extend_from_slice(b"\",\"target\":\"");
+    escape(buffer, target(record));
+    if let Some(context) = context {
+        buffer.extend_from_slice(b"\",\"context\":\"");
+        escape(buffer, context);
+    }
+    buffer.extend_from_slice(b"\",\"message\":\"");
+    let mut truncated = false;
+    if limit.is_some() {
+        let start = buffer.len();
+        truncated = write_limited(buffer, start, *record.args(), limit);
+        let message = buffer.split_off(start);
+        escape(buffer, &String::from_utf8_lossy(&message));
+    } else {
+        // A formatting error leaves the message as it was written until then, which is still
+        // delivered.
+        let _ = Escaper(buffer).write_fmt(*record.args());
+    }
+    let _ = write!(Writer(buffer), "\",\"timestamp\":{timestamp}}}");
+    truncated
+}
+
+/// Escapes everything written so that it can be placed within a JSON string.
+struct Escaper<'a>(&'a mut Vec<u8>);
+
+impl fmt::Write for Escaper<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        escape(self.0, s);
+        Ok(())
+    }
+}
+
+fn escape(buffer: &mut Vec<u8>, s: &str) {
+    for byte in s.bytes() {
+        match byte {
+            b'"' => buffer.extend_from_slice(b"\\\""),
+            b'\\' => buffer.extend_from_slice(b"\\\\"),
+            b'\n' => buffer.extend_from_slice(b"\\n"),
+            b'\r' => buffer.extend_from_slice(b"\\r"),
+            b'\t' => buffer.extend_from_slice(b"\\t"),
+            0x00..=0x1F => {
+                let _ = write!(Writer(buffer), "\\u{byte:04x}");
+            }
+            // Multi-byte UTF-8 sequences never contain bytes that need escaping.
+            _ => buffer.push(byte),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use alloc::string::ToString;
+#[cfg(feature = "std")]
+use core::cell::Cell;
+use core::ffi::{c_char, c_void};
+#[cfg(feature = "std")]
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use core::mem;
+use core::ptr::NonNull;
+
+use log::kv::{self, Key, Source, Value, VisitSource};
+use log::Record;
+
+use crate::to_c_string;
+
+/// A callback receiving each structured key-value pair of a record.
+///
+/// The arguments are the type-erased user data pointer, the null-terminated key, and the
+/// null-terminated value formatted with its [Display](core::fmt::Display) implementation. Both
+/// strings are only valid for the duration of the call.
+///
+/// The pairs of a record are passed one after another on the thread that logged it, right
+/// before the record itself is passed to the main callback.
+pub type KvCallback = extern "C" fn(Option<NonNull<c_void>>, *const c_char, *const c_char);
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// The pairs of the record being delivered on the current thread, if there is one.
+    static CURRENT: Cell<Option<*const (dyn Source + 'static)>> = const { Cell::new(None) };
+}
+
+/// Pass each key-value pair of the record to the callback.
+pub(crate) fn forward(callback: KvCallback, data: Option<NonNull<c_void>>, record: &Record) {
+    visit(callback, data, record.key_values());
+}
+
+fn visit(callback: KvCallback, data: Option<NonNull<c_void>>, source: &dyn Source) {
+    // Visiting only fails if the visitor does, which this one never does.
+    let _ = source.visit(&mut Forwarder { callback, data });
+}
+
+/// Makes the pairs of a record available to [forward_current] until dropped.
+#[cfg(feature = "std")]
+pub(crate) struct Current<'a> {
+    previous: Option<*const (dyn Source + 'static)>,
+    record: PhantomData<&'a Record<'a>>,
+}
+
+#[cfg(feature = "std")]
+impl Drop for Current<'_> {
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|current| current.set(self.previous));
+    }
+}
+
+/// Make the pairs of the record available to [forward_current] while it is delivered.
+#[cfg(feature = "std")]
+pub(crate) fn set_current<'a>(record: &'a Record<'a>) -> Option<Current<'a>> {
+    let source: *const (dyn Source + 'a) = record.key_values();
+    // Safety: The pointer is only dereferenced while it is set, which the guard limits to the
+    // lifetime of the record.
+    let source = unsafe {
+        mem::transmute::<*const (dyn Source + 'a), *const (dyn Source + 'static)>(source)
+    };
+
+    CURRENT
+        .try_with(|current| Current {
+            previous: current.replace(Some(source)),
+            record: PhantomData,
+        })
+        .ok()
+}
+
+/// Pass each key-value pair of the record being delivered on the current thread to the
+/// callback, returning whether there was such a record.
+#[cfg(feature = "std")]
+pub(crate) fn forward_current(callback: KvCallback, data: Option<NonNull<c_void>>) -> bool {
+    let Some(source) = CURRENT.try_with(Cell::get).ok().flatten() else {
+        return false;
+    };
+    // Safety: The pairs are only set while their record is being delivered.
+    visit(callback, data, unsafe { &*source });
+    true
+}
+
+struct Forwarder {
+    callback: KvCallback,
+    data: Option<NonNull<c_void>>,
+}
+
+impl<'kvs> VisitSource<'kvs> for Forwarder {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let key = to_c_string(key.as_str());
+        let value = to_c_string(value.to_string());
+
+        (self.callback)(self.data, key.as_ptr(), value.as_ptr());
+
+        Ok(())
+    }
+}
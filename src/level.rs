@@ -0,0 +1,151 @@
+use core::ffi::CStr;
+
+use log::{Level, LevelFilter};
+
+// Every conversion between levels and the integers passed across the ABI is in this module,
+// and matches exhaustively, so that a level added to `log` cannot be mapped to the wrong
+// integer without this failing to compile.
+
+/// The names of the levels from [Level::Error] to [Level::Trace], as [Level] displays them.
+pub(crate) const LEVEL_NAMES: [&CStr; 5] = [c"ERROR", c"WARN", c"INFO", c"DEBUG", c"TRACE"];
+
+/// The bits of every level in a level mask.
+pub(crate) const ALL_LEVELS: u8 = 0b1_1111;
+
+/// The index of the level in the arrays holding something for each level, from zero for
+/// [Level::Error] to four for [Level::Trace].
+pub(crate) const fn level_index(level: Level) -> usize {
+    match level {
+        Level::Error => 0,
+        Level::Warn => 1,
+        Level::Info => 2,
+        Level::Debug => 3,
+        Level::Trace => 4,
+    }
+}
+
+/// The bit of the level in a level mask.
+pub(crate) const fn level_bit(level: Level) -> u8 {
+    1 << level_index(level)
+}
+
+/// The level filter with the integer level, if there is one.
+pub(crate) const fn level_filter(level: u8) -> Option<LevelFilter> {
+    match level {
+        0 => Some(LevelFilter::Off),
+        1 => Some(LevelFilter::Error),
+        2 => Some(LevelFilter::Warn),
+        3 => Some(LevelFilter::Info),
+        4 => Some(LevelFilter::Debug),
+        5 => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+/// The integer level of the level filter, the inverse of [level_filter].
+pub(crate) const fn filter_to_u8(level: LevelFilter) -> u8 {
+    match level {
+        LevelFilter::Off => 0,
+        LevelFilter::Error => 1,
+        LevelFilter::Warn => 2,
+        LevelFilter::Info => 3,
+        LevelFilter::Debug => 4,
+        LevelFilter::Trace => 5,
+    }
+}
+
+/// The level of a record, as passed to callbacks.
+///
+/// Unlike [Level], the discriminants of this are part of the C ABI and will not change, so a C
+/// host can declare it as:
+///
+/// ```c
+/// typedef enum {
+///     FFI_LEVEL_ERROR = 1,
+///     FFI_LEVEL_WARN = 2,
+///     FFI_LEVEL_INFO = 3,
+///     FFI_LEVEL_DEBUG = 4,
+///     FFI_LEVEL_TRACE = 5,
+/// } FfiLevel;
+/// ```
+///
+/// These match the integer levels used by the functions in [crate::ffi]. Every level of [log]
+/// converts to one of these and back to itself:
+///
+/// ```
+/// use ffi_logger::FfiLevel;
+/// use log::Level;
+///
+/// assert_eq!(FfiLevel::Warn as i32, 2);
+/// assert_eq!(FfiLevel::from(Level::Trace), FfiLevel::Trace);
+/// assert_eq!(Level::from(FfiLevel::Error), Level::Error);
+///
+/// for (level, integer) in Level::iter().zip(1..) {
+///     let ffi = FfiLevel::from(level);
+///     assert_eq!(ffi as i32, integer);
+///     assert_eq!(Level::from(ffi), level);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(C)]
+pub enum FfiLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl FfiLevel {
+    /// The syslog severity of the level, as passed to a
+    /// [SyslogCallback](crate::SyslogCallback).
+    ///
+    /// This mapping will not change:
+    ///
+    /// | `FfiLevel` | Severity            |
+    /// |------------|---------------------|
+    /// | `Error`    | 3 (`LOG_ERR`)       |
+    /// | `Warn`     | 4 (`LOG_WARNING`)   |
+    /// | `Info`     | 6 (`LOG_INFO`)      |
+    /// | `Debug`    | 7 (`LOG_DEBUG`)     |
+    /// | `Trace`    | 7 (`LOG_DEBUG`)     |
+    ///
+    /// ```
+    /// use ffi_logger::FfiLevel;
+    ///
+    /// assert_eq!(FfiLevel::Warn.syslog_severity(), 4);
+    /// assert_eq!(FfiLevel::Trace.syslog_severity(), 7);
+    /// ```
+    pub const fn syslog_severity(self) -> u8 {
+        match self {
+            FfiLevel::Error => 3,
+            FfiLevel::Warn => 4,
+            FfiLevel::Info => 6,
+            FfiLevel::Debug | FfiLevel::Trace => 7,
+        }
+    }
+}
+
+impl From<Level> for FfiLevel {
+    fn from(level: Level) -> FfiLevel {
+        match level {
+            Level::Error => FfiLevel::Error,
+            Level::Warn => FfiLevel::Warn,
+            Level::Info => FfiLevel::Info,
+            Level::Debug => FfiLevel::Debug,
+            Level::Trace => FfiLevel::Trace,
+        }
+    }
+}
+
+impl From<FfiLevel> for Level {
+    fn from(level: FfiLevel) -> Level {
+        match level {
+            FfiLevel::Error => Level::Error,
+            FfiLevel::Warn => Level::Warn,
+            FfiLevel::Info => Level::Info,
+            FfiLevel::Debug => Level::Debug,
+            FfiLevel::Trace => Level::Trace,
+        }
+    }
+}
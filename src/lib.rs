@@ -1,17 +1,660 @@
-#![doc = include_str!("../README.md")]
+// The examples in the README use the standard library.
+#![cfg_attr(feature = "std", doc = include_str!("../README.md"))]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use std::ffi::{c_char, c_void, CString};
-use std::io::Write;
-use std::ptr::NonNull;
+extern crate alloc;
+
+#[cfg(any(feature = "std", feature = "json"))]
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::ffi::CString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_int, c_void, CStr};
+use core::fmt::{self, Write as _};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use buffer::Writer;
+use closure::OwnedClosure;
+use dedup::{Dedup, Seen};
+use dispatch::{Dispatcher, Header, RecordStrings};
+#[cfg(feature = "std")]
+use filter::Directives;
+use filter::{TargetLevels, TargetLists};
+use format::Formatter;
+use owned::OwnedData;
+use poison::Poison;
+use rate_limit::RateLimits;
+use recent::Recent;
+#[cfg(feature = "std")]
+use route::Route;
+use sample::Sampling;
+use sync::{Mutex, RwLock};
+#[cfg(feature = "std")]
+use worker::{Queued, Worker};
+
+#[cfg(target_os = "android")]
+mod android;
+mod backpressure;
+mod batch;
+mod buffer;
+mod builder;
+mod clock;
+mod closure;
+mod color;
+mod config;
+mod dedup;
+mod dispatch;
+mod error;
+pub mod ffi;
+mod filter;
+mod format;
+mod handle;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "kv")]
+mod kv;
+mod level;
+#[cfg(all(feature = "oslog", target_vendor = "apple"))]
+mod oslog;
+mod owned;
+#[cfg(feature = "std")]
+mod panic_hook;
+mod poison;
+mod rate_limit;
+mod recent;
+mod record;
+mod route;
+mod sample;
+mod sync;
+mod thread;
+#[cfg(feature = "std")]
+mod worker;
+
+#[cfg(target_os = "android")]
+pub use android::AndroidLogWrite;
+pub use buffer::InteriorNul;
+pub use builder::FfiLoggerBuilder;
+pub use clock::TimestampFormat;
+pub use config::FfiLoggerConfig;
+pub use dispatch::Rejected;
+pub use error::FfiLoggerError;
+pub use handle::{LogGuard, LogHandle};
+#[cfg(feature = "kv")]
+pub use kv::KvCallback;
+pub use level::FfiLevel;
+use level::{level_bit, level_filter, level_index, ALL_LEVELS};
+#[cfg(all(feature = "oslog", target_vendor = "apple"))]
+pub use oslog::OsLogWrite;
+pub use record::{FfiLogRecord, FFI_LOG_RECORD_VERSION};
+pub use route::SinkId;
+#[cfg(feature = "std")]
+pub use worker::Overflow;
+
+/// The original callback shape, receiving only the user data and a null-terminated message.
+///
+/// See [FfiLogger::new] for the contract of the callback.
+///
+/// When used through [log::Log], any interior null bytes in the message are by default replaced
+/// with the ASCII substitute character (`0x1A`) so that the whole message is still delivered.
+/// This can be changed with [FfiLoggerBuilder::interior_nul].
+///
+/// ```
+/// use std::ffi::{c_char, c_void, CStr};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::FfiLogger;
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+///     let message = unsafe { CStr::from_ptr(message) };
+///     assert_eq!(message.to_bytes(), b"a\x1Ab");
+///     message.to_bytes().len() as isize
+/// }
+///
+/// let handle = unsafe { FfiLogger::init(sink, None, log::LevelFilter::Info) }.unwrap();
+/// for _ in 0..3 {
+///     log::info!("a{}b", '\0');
+/// }
+/// assert_eq!(handle.short_writes(), 0);
+/// ```
+pub type Callback = extern "C" fn(Option<NonNull<c_void>>, *const c_char) -> isize;
+
+/// A callback that also receives the metadata of each record, as an [FfiLogRecord].
+///
+/// The arguments are the type-erased user data pointer, and the record, which is never null and
+/// only valid for the duration of the call. The return value has the same meaning as for
+/// [Callback].
+///
+/// This is only delivered to when the [FfiLogger] is used directly as a [log::Log]
+/// implementation, as writing through [std::io::Write] carries no record metadata.
+///
+/// ```
+/// use std::ffi::{c_void, CStr};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLevel, FfiLogRecord, FfiLogger, FFI_LOG_RECORD_VERSION};
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+///     let record = unsafe { &*record };
+///     assert_eq!(record.version, FFI_LOG_RECORD_VERSION);
+///     assert_eq!(record.size as usize, size_of::<FfiLogRecord>());
+///     assert_eq!(record.level, FfiLevel::Warn);
+///     assert_eq!(unsafe { CStr::from_ptr(record.target) }, c"app::db");
+///     assert_eq!(unsafe { CStr::from_ptr(record.message) }, c"slow query");
+///     record.message_len as isize
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Record(sink), None) };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::warn!(target: "app::db", "slow query");
+/// ```
+pub type RecordCallback = extern "C" fn(Option<NonNull<c_void>>, *const FfiLogRecord) -> isize;
+
+/// A callback receiving the level and the message, along with its length.
+///
+/// The arguments are, in order:
+/// * The type-erased user data pointer.
+/// * The [FfiLevel] of the record.
+/// * The null-terminated message.
+/// * The length of the message in bytes, not including the null terminator.
+///
+/// Like every callback used through [log::Log], the message is formatted into a buffer that
+/// each thread reuses, so that steady-state logging does not allocate. Unlike [RecordCallback], no
+/// other metadata is allocated either. The message is only valid for the duration of the call.
+/// The return value has the same meaning as for [Callback].
+///
+/// ```
+/// use std::ffi::{c_char, c_void};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger};
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, _: FfiLevel, message: *const c_char, len: usize) -> isize {
+///     let message = unsafe { std::slice::from_raw_parts(message.cast::<u8>(), len + 1) };
+///     assert_eq!(message, b"answer: 42\0");
+///     len as isize
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Buffer(sink), None) };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!("answer: {}", 42);
+/// ```
+pub type BufferCallback =
+    extern "C" fn(Option<NonNull<c_void>>, FfiLevel, *const c_char, usize) -> isize;
+
+/// A callback receiving the level and the exact bytes of the message.
+///
+/// The arguments are, in order:
+/// * The type-erased user data pointer.
+/// * The [FfiLevel] of the record.
+/// * The bytes of the message.
+/// * The number of bytes in the message.
+///
+/// Unlike the other callbacks, interior null bytes are not replaced, and the message is not
+/// guaranteed to be null-terminated, so the length must be used. As with [BufferCallback], the
+/// message is only valid for the duration of the call, and the return value has the same
+/// meaning as for [Callback].
+///
+/// This is the shape to use for sinks taking a `std::string_view` or another pointer and
+/// length pair, which C can declare with `const char*` as the message type:
+///
+/// ```c
+/// intptr_t sink(void* data, FfiLevel level, const char* message, size_t len);
+/// ```
+///
+/// ```
+/// use std::ffi::c_void;
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger};
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, _: FfiLevel, message: *const u8, len: usize) -> isize {
+///     let message = unsafe { std::slice::from_raw_parts(message, len) };
+///     assert_eq!(message, b"frame: \0\x01\0");
+///     len as isize
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Raw(sink), None) };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!("frame: {}", "\0\x01\0");
+/// ```
+///
+/// A message that is only a string literal, such as `log::info!("ready")`, is passed without
+/// being copied when every callback of its level is a raw callback, and the logger adds nothing
+/// to it such as a timestamp or a trailing newline:
+///
+/// ```
+/// use std::ffi::c_void;
+/// use std::ptr::{self, NonNull};
+/// use std::sync::atomic::{AtomicPtr, Ordering};
+///
+/// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger};
+/// use log::{Log, Record};
+///
+/// static LITERAL: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, _: FfiLevel, message: *const u8, len: usize) -> isize {
+///     assert_eq!(message, LITERAL.load(Ordering::Relaxed).cast_const());
+///     len as isize
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Raw(sink), None) };
+/// let args = format_args!("ready");
+/// LITERAL.store(args.as_str().unwrap().as_ptr().cast_mut(), Ordering::Relaxed);
+/// logger.log(&Record::builder().args(args).level(log::Level::Error).build());
+/// ```
+pub type RawCallback = extern "C" fn(Option<NonNull<c_void>>, FfiLevel, *const u8, usize) -> isize;
+
+/// A callback receiving the syslog severity of the record and the message, along with its
+/// length.
+///
+/// This is the same as [BufferCallback], except that the level is passed as the syslog severity
+/// (`0` to `7`) given by [FfiLevel::syslog_severity], so that it can be passed on to a syslog
+/// daemon as it is.
+///
+/// ```
+/// use std::ffi::{c_char, c_int, c_void};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLogger};
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, severity: c_int, _: *const c_char, len: usize) -> isize {
+///     assert_eq!(severity, 4);
+///     len as isize
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Syslog(sink), None) };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::warn!("disk almost full");
+/// ```
+pub type SyslogCallback =
+    extern "C" fn(Option<NonNull<c_void>>, c_int, *const c_char, usize) -> isize;
+
+/// A callback receiving the level and the message as UTF-16, along with its length, for sinks
+/// writing to the wide-character Windows APIs.
+///
+/// The arguments are, in order:
+/// * The type-erased user data pointer.
+/// * The [FfiLevel] of the record.
+/// * The null-terminated UTF-16 message.
+/// * The length of the message in code units, not including the null terminator.
+///
+/// Interior null code units are handled as set by [FfiLoggerBuilder::interior_nul]. Unlike
+/// [BufferCallback], the message is converted into a new allocation, which is shared by every
+/// wide-character callback it is delivered to. The message is only valid for the duration of
+/// the call, and the return value has the same meaning as for [Callback], counting code units
+/// instead of bytes.
+///
+/// ```
+/// use std::ffi::c_void;
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLevel, FfiLogger};
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, _: FfiLevel, message: *const u16, len: usize) -> isize {
+///     let message = unsafe { std::slice::from_raw_parts(message, len + 1) };
+///     assert_eq!(message, [0x68, 0xE9, 0]);
+///     len as isize
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Wide(sink), None) };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!("h\u{e9}");
+/// ```
+#[cfg(windows)]
+pub type WideCallback =
+    extern "C" fn(Option<NonNull<c_void>>, FfiLevel, *const u16, usize) -> isize;
+
+/// A callback receiving a second pointer besides the user data, along with the message.
+///
+/// The arguments are the type-erased user data pointer the callback was given, the context
+/// pointer set with [LogHandle::set_context_ptr], and the null-terminated message. The context
+/// starts out null, and can be changed at any time without touching the user data, such as to
+/// identify the operation in progress while the user data stays the sink. It is unrelated to the
+/// context set with [LogHandle::set_context], which is written into messages. Otherwise this is
+/// the same as [Callback].
+///
+/// ```
+/// use std::ffi::{c_char, c_void};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+///
+/// extern "C" fn sink(data: Option<NonNull<c_void>>, context: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+///     let data = unsafe { *data.unwrap().cast::<u32>().as_ref() };
+///     let context = context.map(|context| unsafe { *context.cast::<u32>().as_ref() });
+///     assert_eq!((data, context), (1, Some(2)));
+///     0
+/// }
+///
+/// let (data, context): (&'static u32, &'static u32) = (&1, &2);
+/// let logger = unsafe {
+///     FfiLogger::with_callback(FfiCallback::Context(sink), Some(NonNull::from(data).cast()))
+/// };
+/// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+/// log::set_logger(logger).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// let handle = LogHandle::new(logger);
+/// unsafe { handle.set_context_ptr(Some(NonNull::from(context).cast())) };
+/// log::info!("within the operation");
+/// assert_eq!(handle.last_error(), None);
+/// ```
+pub type ContextCallback =
+    extern "C" fn(Option<NonNull<c_void>>, Option<NonNull<c_void>>, *const c_char) -> isize;
+
+/// A callback receiving an integer id in place of user data, along with the message.
+///
+/// This is for bindings that identify their sink by an id, such as an index into a table of a
+/// managed language, rather than by a pointer. The id given to [FfiLogger::with_id], or along
+/// with the callback in [FfiCallback::Id], is passed as it is, including zero. It is only ever
+/// passed to this callback, and not to the others receiving user data. Otherwise this is the
+/// same as [Callback].
+///
+/// ```
+/// use std::ffi::{c_char, CStr};
+///
+/// use ffi_logger::FfiLogger;
+///
+/// extern "C" fn sink(id: usize, message: *const c_char) -> isize {
+///     assert_eq!(id, 0);
+///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"to sink zero");
+///     0
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_id(sink, 0) };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!("to sink zero");
+/// ```
+pub type IdCallback = extern "C" fn(usize, *const c_char) -> isize;
+
+/// A callback taking ownership of each message, for sinks that keep messages after the call
+/// returns, such as by queueing them for another thread.
+///
+/// The arguments are the type-erased user data pointer and the null-terminated message, which
+/// is allocated for each call. **The message belongs to the callback, which must free it with
+/// [ffi_logger_free_message](ffi::ffi_logger_free_message) exactly once, and not with `free` or
+/// any other allocator.** This is the case whatever the callback returns, and the return value
+/// has the same meaning as for [Callback]. A null message, such as for
+/// [FfiLoggerBuilder::shutdown_notification], does not need to be freed.
+///
+/// ```
+/// use std::ffi::{c_char, c_void, CStr};
+/// use std::ptr::NonNull;
+/// use std::sync::Mutex;
+///
+/// use ffi_logger::ffi::ffi_logger_free_message;
+/// use ffi_logger::{FfiCallback, FfiLogger};
+///
+/// struct Queued(*mut c_char);
+/// unsafe impl Send for Queued {}
+///
+/// static QUEUE: Mutex<Vec<Queued>> = Mutex::new(Vec::new());
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *mut c_char) -> isize {
+///     let len = unsafe { CStr::from_ptr(message) }.to_bytes().len();
+///     QUEUE.lock().unwrap().push(Queued(message));
+///     len as isize
+/// }
+///
+/// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Owned(sink), None) };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!("kept for later");
+/// for Queued(message) in QUEUE.lock().unwrap().drain(..) {
+///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"kept for later");
+///     unsafe { ffi_logger_free_message(message) };
+/// }
+/// ```
+pub type OwnedCallback = extern "C" fn(Option<NonNull<c_void>>, *mut c_char) -> isize;
+
+/// A callback receiving records in batches, so that the fixed cost of each call to the sink is
+/// shared by many records.
+///
+/// The arguments are the type-erased user data pointer, a pointer to the first of the records,
+/// and the number of records, which are only valid for the duration of the call. Each record is
+/// the same as for [RecordCallback]. The return value is the number of records consumed, or a
+/// negative error code, which is recorded like that of [Callback].
+///
+/// Records are collected until [FfiLoggerBuilder::batch] says to deliver them, which is after
+/// every record by default, and [log::Log::flush] or [LogHandle::deinit] deliver whatever is
+/// pending. A batch callback passed to [FfiLoggerBuilder::level_callback] is not called.
+///
+/// ```
+/// use std::ffi::c_void;
+/// use std::ptr::NonNull;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::time::Duration;
+///
+/// use ffi_logger::{FfiCallback, FfiLogRecord, FfiLogger, LogHandle};
+///
+/// static BATCHES: AtomicUsize = AtomicUsize::new(0);
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const FfiLogRecord, count: usize) -> isize {
+///     BATCHES.fetch_add(1, Ordering::Relaxed);
+///     count as isize
+/// }
+///
+/// let logger = unsafe {
+///     FfiLogger::builder(FfiCallback::Batch(sink))
+///         .batch(3, Duration::from_secs(60))
+///         .build()
+/// };
+/// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+/// log::set_logger(logger).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// for i in 0..4 {
+///     log::info!("line {i}");
+/// }
+/// assert_eq!(BATCHES.load(Ordering::Relaxed), 1);
+/// LogHandle::new(logger).flush();
+/// assert_eq!(BATCHES.load(Ordering::Relaxed), 2);
+/// ```
+pub type BatchCallback =
+    extern "C" fn(Option<NonNull<c_void>>, *const FfiLogRecord, usize) -> isize;
+
+/// A callback writing the message delivered for each record, instead of the built-in format.
+///
+/// The arguments are, in order:
+/// * The type-erased user data pointer given along with it.
+/// * The record, whose message is only what was logged. It is cut off at the
+///   [message_ceiling](FfiLoggerBuilder::message_ceiling), and its interior null bytes are
+///   handled as set with [interior_nul](FfiLoggerBuilder::interior_nul).
+/// * The buffer to write into.
+/// * The number of bytes the buffer can hold.
+///
+/// The return value is the number of bytes the message takes. If that is more than fit, the
+/// callback is called again with a buffer large enough, as with `snprintf`, unless there is a
+/// [fixed_buffer](FfiLoggerBuilder::fixed_buffer), in which case what fit is delivered. A
+/// negative return value falls back to the built-in format for the record. The message does not
+/// need to be null-terminated, and the record and buffer are only valid for the duration of the
+/// call.
+///
+/// ```
+/// use std::ffi::{c_char, c_void, CStr};
+/// use std::ptr::NonNull;
+///
+/// use ffi_logger::{FfiCallback, FfiLogRecord, FfiLogger};
+///
+/// extern "C" fn logfmt(_: Option<NonNull<c_void>>, record: *const FfiLogRecord, buf: *mut u8, cap: usize) -> isize {
+///     let record = unsafe { &*record };
+///     let target = unsafe { CStr::from_ptr(record.target) }.to_string_lossy();
+///     let message = unsafe { CStr::from_ptr(record.message) }.to_string_lossy();
+///     let line = format!("level={:?} target={target} msg={message:?}", record.level);
+///
+///     if line.len() <= cap {
+///         unsafe { buf.copy_from_nonoverlapping(line.as_ptr(), line.len()) };
+///     }
+///     line.len() as isize
+/// }
+///
+/// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+///     let message = unsafe { CStr::from_ptr(message) };
+///     assert_eq!(message, c"level=Info target=app msg=\"started\"");
+///     0
+/// }
+///
+/// let logger = unsafe {
+///     FfiLogger::builder(FfiCallback::Message(sink))
+///         .formatter(logfmt, None)
+///         .build()
+/// };
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!(target: "app", "started");
+/// ```
+pub type FormatCallback =
+    extern "C" fn(Option<NonNull<c_void>>, *const FfiLogRecord, *mut u8, usize) -> isize;
+
+/// A callback asking the sink to flush any output it has buffered, receiving the type-erased
+/// user data pointer.
+pub type FlushCallback = extern "C" fn(Option<NonNull<c_void>>);
+
+/// A callback deciding whether records are logged, after the level and target filters have
+/// let them through.
+///
+/// The arguments are the type-erased user data pointer, the [FfiLevel] of the record, and the
+/// bytes of its target along with their length. The target is not null-terminated, so that
+/// no allocation is needed to call this. Returning `false` skips the record before its message
+/// is formatted. This is called for every record that passes the other filters, and from
+/// [log::Log::enabled], so it should be cheap.
+pub type FilterCallback =
+    extern "C" fn(Option<NonNull<c_void>>, FfiLevel, *const u8, usize) -> bool;
+
+/// A callback told when a message was dropped instead of delivered.
+///
+/// The arguments are the type-erased user data pointer, the [FfiLevel] of the message, and a
+/// null-terminated description of why it was dropped, which is valid for the lifetime of the
+/// program. Messages logged while this is being called are not reported to it again, so that a
+/// callback that logs cannot cause more reports without end.
+pub type ErrorCallback = extern "C" fn(Option<NonNull<c_void>>, FfiLevel, *const c_char);
+
+/// The callback an [FfiLogger] delivers messages through.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum FfiCallback {
+    /// Receives only the message. See [Callback].
+    Message(Callback),
+    /// Receives the message along with all of the metadata of the record. See
+    /// [RecordCallback].
+    Record(RecordCallback),
+    /// Receives the level along with the message, without allocating. See [BufferCallback].
+    Buffer(BufferCallback),
+    /// Receives the level along with the exact bytes of the message. See [RawCallback].
+    Raw(RawCallback),
+    /// Receives the syslog severity along with the message, without allocating. See
+    /// [SyslogCallback].
+    Syslog(SyslogCallback),
+    /// Receives the context pointer along with the message. See [ContextCallback].
+    Context(ContextCallback),
+    /// Receives the integer id given with it instead of user data, along with the message. See
+    /// [IdCallback].
+    Id(IdCallback, usize),
+    /// Receives only the message, which it takes ownership of. See [OwnedCallback].
+    Owned(OwnedCallback),
+    /// Receives records in batches. See [BatchCallback].
+    Batch(BatchCallback),
+    /// Receives the level along with the message as UTF-16. See [WideCallback].
+    #[cfg(windows)]
+    Wide(WideCallback),
+}
 
 #[derive(Debug)]
 pub struct FfiLogger {
-    data: Option<NonNull<c_void>>,
-    logger: extern "C" fn(Option<NonNull<c_void>>, *const c_char) -> isize,
+    dispatcher: Arc<Dispatcher>,
+    #[cfg(feature = "std")]
+    worker: Option<Worker>,
+    #[cfg(feature = "kv")]
+    kv: Option<KvCallback>,
+    timestamps: Option<TimestampFormat>,
+    /// The number of the next record, if records are numbered.
+    sequence: Option<AtomicU64>,
+    #[cfg(feature = "json")]
+    json: bool,
+    flush: Option<FlushCallback>,
+    filter: Option<FilterCallback>,
+    formatter: Option<Formatter>,
+    component: Option<&'static CStr>,
+    /// Whether messages end with a newline.
+    newline: bool,
+    /// What follows each field placed before the message.
+    separator: &'static CStr,
+    /// The names of the levels, indexed by their discriminant minus one.
+    level_names: [&'static CStr; 5],
+    /// Whether messages are written to stderr while there is no callback.
+    #[cfg(feature = "std")]
+    stderr_fallback: AtomicBool,
+    /// Whether the logger's callback is passed the user data of the thread logging, if it has
+    /// any.
+    #[cfg(feature = "std")]
+    thread_data: bool,
+    /// The number of records of each level delivered, indexed by its discriminant minus one.
+    level_counts: [AtomicU64; 5],
+    /// Set by [LogHandle::deinit] in debug builds, to count records logged afterwards.
+    poison: Poison,
+    /// Whether the callback is called with a null message by [LogHandle::deinit].
+    shutdown_notification: bool,
+    rate_limits: RateLimits,
+    sampling: Sampling,
+    dedup: Option<Dedup>,
+    ansi_colors: bool,
+    /// The longest message in bytes that is delivered without being cut off.
+    max_len: Option<usize>,
+    /// The longest message in bytes that is ever formatted, whatever `max_len` is.
+    ceiling: usize,
+    /// The size each thread reserves for the buffer it formats messages into, if any.
+    buffer_size: Option<usize>,
+    /// The most a message can take up in a fixed buffer, besides its null terminator and any
+    /// newline.
+    fixed_room: Option<usize>,
+    /// The most verbose [LevelFilter] that is passed on, stored as its discriminant.
+    level: AtomicU8,
+    /// Whether anything is logged at all, so that logging can be muted for a while.
+    on: AtomicBool,
+    /// Whether [LogHandle::set_filter] sets [log::set_max_level] as well, which it only does
+    /// for the constructors that set it in the first place.
+    sets_max_level: AtomicBool,
+    /// The levels that are passed on, with bit `n - 1` set for the level with discriminant `n`.
+    level_mask: AtomicU8,
+    /// Overrides of `level` for specific targets.
+    target_levels: TargetLevels,
+    target_lists: TargetLists,
+    /// Written before the message of each record, if set. This is cloned out of the lock
+    /// before formatting, in case formatting the message sets it.
+    context: RwLock<Option<Arc<str>>>,
+    /// The most recent messages, if they are kept.
+    recent: Option<Recent>,
+    /// The user data, if created with [FfiLogger::with_owned_data] and not yet dropped by
+    /// [LogHandle::deinit]. This is dropped after the worker thread, which may still be using it.
+    owned: Mutex<Option<OwnedData>>,
+    /// The closure passed as the user data, if created with [FfiLogger::from_closure]. This is
+    /// dropped after the worker thread, which may still be calling it.
+    closure: Option<OwnedClosure>,
 }
 
-unsafe impl Send for FfiLogger {}
-
 impl FfiLogger {
     /// Create an instance of an FFI logger.
     ///
@@ -21,52 +664,1124 @@ impl FfiLogger {
     /// The callback takes in an optional type-erased user data pointer, and a null-terminated
     /// string to be logged. The return value of the callback represents the number of bytes
     /// written if zero or larger, and if negative represents a user defined error code.
+    /// When used as a [log::Log] implementation, errors and short writes are reported through
+    /// [LogHandle].
     ///
     /// The callback may be called from different threads, meaning that it may be used
     /// in parallel to the C FFI.
     ///
     /// Each call to the logger should flush the output so that each logged message is not
     /// interleaved.
-    /// 
+    ///
+    /// When used as a [log::Log] implementation, interior null bytes in messages are replaced
+    /// with `0x1A`. Use [FfiLogger::builder] with [FfiLoggerBuilder::interior_nul] to change this.
+    /// When written to through [std::io::Write], buffers with interior nulls are rejected with an
+    /// error instead.
+    ///
     /// Once the Rust library is done, disable the logger with [log::set_max_level] to [log::LevelFilter::Off].
     /// Then it is safe to do any deallocation on the FFI side.
-    /// 
+    ///
     /// # Safety
     /// * The callback & data must be safe to be used across different threads.
     /// * Once [log::set_max_level] is set to [log::LevelFilter::Off], Rust code must not be called into again.
-    pub unsafe fn new(
-        logger: extern "C" fn(Option<NonNull<c_void>>, *const c_char) -> isize,
+    pub unsafe fn new(logger: Callback, data: Option<NonNull<c_void>>) -> FfiLogger {
+        Self::with_callback(FfiCallback::Message(logger), data)
+    }
+
+    /// Create an instance of an FFI logger with any of the supported callback shapes.
+    ///
+    /// Apart from the arguments passed to the callback, this behaves the same as [FfiLogger::new].
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply.
+    pub unsafe fn with_callback(logger: FfiCallback, data: Option<NonNull<c_void>>) -> FfiLogger {
+        Self::builder(logger).data(data).build()
+    }
+
+    /// Create a logger whose callback is passed an integer id instead of a user data pointer.
+    ///
+    /// The logger has no user data, so the callbacks receiving it, such as the
+    /// [flush callback](FfiLoggerBuilder::flush_callback), are passed null, and
+    /// [LogHandle::deinit] returns [None]. Use [FfiLogger::builder] with [FfiCallback::Id] for
+    /// the other options.
+    ///
+    /// # Safety
+    /// The callback must be safe to be used across different threads, and the other requirements
+    /// of [FfiLogger::new] apply.
+    pub unsafe fn with_id(logger: IdCallback, id: usize) -> FfiLogger {
+        Self::builder(FfiCallback::Id(logger, id)).build()
+    }
+
+    /// Create a logger that passes the level and message of each record to a Rust closure.
+    ///
+    /// This is mostly useful for testing code that logs through an [FfiLogger], without writing
+    /// an `extern "C"` callback. The closure is passed to the callback as its user data, and is
+    /// dropped along with the logger, so that data must not be deallocated or used otherwise,
+    /// such as after [FfiLogger::into_data] or [LogHandle::deinit]. If the closure panics, the
+    /// panic is caught and reported as an error, like a callback returning `-1`.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use ffi_logger::FfiLogger;
+    /// use log::Level;
+    ///
+    /// let lines = Arc::new(Mutex::new(Vec::new()));
+    /// let logger = FfiLogger::from_closure({
+    ///     let lines = lines.clone();
+    ///     move |level, message| lines.lock().unwrap().push(format!("{level}: {message}"))
+    /// });
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::warn!("low on {}", "disk");
+    /// assert_eq!(*lines.lock().unwrap(), ["WARN: low on disk"]);
+    /// ```
+    ///
+    /// The closure is passed the message exactly as it was formatted, like a
+    /// [RawCallback], rather than as a C string. It is never copied into a [CString], so
+    /// [InteriorNul] does not apply, and null bytes reach the closure as they are:
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use ffi_logger::FfiLogger;
+    /// use log::Log;
+    ///
+    /// let lines = Arc::new(Mutex::new(Vec::new()));
+    /// let logger = FfiLogger::from_closure({
+    ///     let lines = lines.clone();
+    ///     move |_, message| lines.lock().unwrap().push(message.to_owned())
+    /// });
+    ///
+    /// let record = log::Record::builder()
+    ///     .level(log::Level::Info)
+    ///     .args(format_args!("before\0after"))
+    ///     .build();
+    /// logger.log(&record);
+    /// assert_eq!(*lines.lock().unwrap(), ["before\0after"]);
+    /// ```
+    pub fn from_closure(f: impl Fn(Level, &str) + Send + Sync + 'static) -> FfiLogger {
+        let closure = OwnedClosure::new(f);
+
+        // Safety: The closure is thread-safe, and is only dropped along with the logger.
+        let mut logger = unsafe {
+            Self::builder(FfiCallback::Raw(closure::trampoline))
+                .data(Some(closure.data()))
+                .build()
+        };
+        logger.closure = Some(closure);
+        logger
+    }
+
+    /// Create a logger that discards every message, for testing code that logs through an
+    /// [FfiLogger] without a real sink.
+    ///
+    /// Its callback does nothing and has no user data, but the logger is otherwise like any
+    /// other, so it can be registered and used through a [LogHandle], including being
+    /// deinitialized.
+    ///
+    /// ```
+    /// use ffi_logger::FfiLogger;
+    ///
+    /// let handle = FfiLogger::null().leak_and_register().unwrap();
+    /// log::set_max_level(log::LevelFilter::Trace);
+    ///
+    /// log::error!("nowhere to be seen");
+    /// assert_eq!(handle.error_count(), 0);
+    /// assert_eq!(handle.deinit(), None);
+    /// ```
+    pub fn null() -> FfiLogger {
+        extern "C" fn discard(
+            _: Option<NonNull<c_void>>,
+            _: FfiLevel,
+            _: *const u8,
+            len: usize,
+        ) -> isize {
+            len as isize
+        }
+
+        // Safety: The callback does nothing, and there is no data.
+        unsafe { Self::with_callback(FfiCallback::Raw(discard), None) }
+    }
+
+    /// Create a logger like [FfiLogger::new], failing instead if the callback is null.
+    ///
+    /// This is for callbacks coming from bindings that may pass a null function pointer, which
+    /// would otherwise only surface as logging doing nothing.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiLogger, FfiLoggerError};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     0
+    /// }
+    ///
+    /// assert!(unsafe { FfiLogger::try_new(Some(sink), None) }.is_ok());
+    /// assert_eq!(
+    ///     unsafe { FfiLogger::try_new(None, None) }.unwrap_err(),
+    ///     FfiLoggerError::NullCallback
+    /// );
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply.
+    pub unsafe fn try_new(
+        logger: Option<Callback>,
         data: Option<NonNull<c_void>>,
+    ) -> Result<FfiLogger, FfiLoggerError> {
+        let logger = logger.ok_or(FfiLoggerError::NullCallback)?;
+        Ok(Self::new(logger, data))
+    }
+
+    /// Create a logger that owns its user data, dropping it on [LogHandle::deinit] or along with
+    /// the logger, instead of handing it back to the caller.
+    ///
+    /// The callbacks are passed a pointer to the `T` in the box as their user data. Replacing
+    /// the data with [LogHandle::set_data] leaves it owned by the logger, so it is still dropped
+    /// along with the logger, and must not be freed by the caller.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+    ///
+    /// static DROPPED: AtomicBool = AtomicBool::new(false);
+    ///
+    /// struct Config {
+    ///     prefix: &'static str,
+    /// }
+    ///
+    /// impl Drop for Config {
+    ///     fn drop(&mut self) {
+    ///         DROPPED.store(true, Ordering::Relaxed);
+    ///     }
+    /// }
+    ///
+    /// extern "C" fn sink(data: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     let config = unsafe { data.unwrap().cast::<Config>().as_ref() };
+    ///     assert_eq!(config.prefix, "app");
+    ///     0
+    /// }
+    ///
+    /// let config = Box::new(Config { prefix: "app" });
+    /// let logger = unsafe { FfiLogger::with_owned_data(FfiCallback::Message(sink), config) };
+    /// let handle = LogHandle::new(Box::leak(Box::new(logger)));
+    ///
+    /// assert_eq!(handle.deinit(), None);
+    /// assert!(DROPPED.load(Ordering::Relaxed));
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply to the callback.
+    pub unsafe fn with_owned_data<T: Send + Sync + 'static>(
+        logger: FfiCallback,
+        data: Box<T>,
     ) -> FfiLogger {
-        Self { logger, data }
+        let owned = OwnedData::new(data);
+
+        let logger = Self::builder(logger).data(Some(owned.data())).build();
+        *logger.owned.lock() = Some(owned);
+        logger
+    }
+
+    /// Create a logger, register it with [log::set_logger], and set [log::set_max_level].
+    ///
+    /// The max level of [log] applies to the whole process, and records more verbose than it
+    /// are skipped before reaching any logger. To leave it to code that manages it already, use
+    /// [FfiLogger::init_keep_max_level] instead, which only sets the logger's own level.
+    ///
+    /// This fails if a logger has already been registered, in which case the user data is not
+    /// retained and still belongs to the caller.
+    ///
+    /// Use [LogHandle::into_guard] on the returned handle to deinitialize the logger and
+    /// reclaim the user data once logging is no longer needed.
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply.
+    pub unsafe fn init(
+        logger: Callback,
+        data: Option<NonNull<c_void>>,
+        max_level: LevelFilter,
+    ) -> Result<LogHandle, SetLoggerError> {
+        let handle = Self::new(logger, data).leak_and_register()?;
+        handle
+            .logger()
+            .sets_max_level
+            .store(true, Ordering::Relaxed);
+        log::set_max_level(max_level);
+        Ok(handle)
+    }
+
+    /// Create a logger and register it like [FfiLogger::init], but set its own level with
+    /// [LogHandle::set_level] instead of changing [log::set_max_level].
+    ///
+    /// This is for processes where something else already sets the max level of [log], which
+    /// then still applies on top of the logger's level, so records must pass both. The max level
+    /// is not changed by [LogHandle::set_filter] either. It stays at
+    /// [Off](LevelFilter::Off) if nothing sets it, in which case nothing is logged.
+    /// [LogHandle::max_level] tells which level records are passed on at.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::FfiLogger;
+    /// use log::LevelFilter;
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// log::set_max_level(LevelFilter::Info);
+    /// let handle = unsafe { FfiLogger::init_keep_max_level(sink, None, LevelFilter::Debug) }.unwrap();
+    ///
+    /// assert_eq!(log::max_level(), LevelFilter::Info);
+    /// assert_eq!(handle.level(), LevelFilter::Debug);
+    /// assert_eq!(handle.max_level(), LevelFilter::Info);
+    ///
+    /// assert!(handle.set_filter("trace"));
+    /// assert_eq!(log::max_level(), LevelFilter::Info);
+    /// assert_eq!(handle.level(), LevelFilter::Trace);
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply.
+    pub unsafe fn init_keep_max_level(
+        logger: Callback,
+        data: Option<NonNull<c_void>>,
+        level: LevelFilter,
+    ) -> Result<LogHandle, SetLoggerError> {
+        let handle = Self::new(logger, data).leak_and_register()?;
+        handle.set_level(level);
+        Ok(handle)
+    }
+
+    /// Create a logger like [FfiLogger::init], taking its levels from the `RUST_LOG`
+    /// environment variable.
+    ///
+    /// Only the simple forms of `env_logger` directives are understood: a comma-separated list
+    /// of a level such as `info`, `target=level` pairs, and targets on their own, which allow
+    /// every level. Invalid directives and `/regex` filters are ignored. The level without a
+    /// target is set with [LogHandle::set_level], defaulting to [Error](LevelFilter::Error) as
+    /// in `env_logger`, and the others with [LogHandle::set_target_level]. [log::set_max_level]
+    /// is set to the most verbose of them.
+    /// [LogHandle::set_filter] sets them the same way after the logger was created.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::FfiLogger;
+    /// use log::{Level, LevelFilter, Log, Metadata};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// std::env::set_var("RUST_LOG", "warn,app::db=trace");
+    /// let handle = unsafe { FfiLogger::init_from_env(sink, None) }.unwrap();
+    ///
+    /// assert_eq!(handle.level(), LevelFilter::Warn);
+    /// assert_eq!(log::max_level(), LevelFilter::Trace);
+    /// let debug = |target| Metadata::builder().level(Level::Debug).target(target).build();
+    /// assert!(handle.logger().enabled(&debug("app::db")));
+    /// assert!(!handle.logger().enabled(&debug("app::http")));
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply.
+    #[cfg(feature = "std")]
+    pub unsafe fn init_from_env(
+        logger: Callback,
+        data: Option<NonNull<c_void>>,
+    ) -> Result<LogHandle, SetLoggerError> {
+        let spec = std::env::var("RUST_LOG").unwrap_or_default();
+        let directives = Directives::parse(&spec);
+
+        let handle = Self::new(logger, data).leak_and_register()?;
+        handle.set_level(directives.level.unwrap_or(LevelFilter::Error));
+        for &(target, level) in &directives.targets {
+            handle.set_target_level(target, level);
+        }
+        handle
+            .logger()
+            .sets_max_level
+            .store(true, Ordering::Relaxed);
+        log::set_max_level(directives.max_level(LevelFilter::Error));
+        Ok(handle)
+    }
+
+    /// Create a logger like [FfiLogger::init], which also delivers the events of crates using
+    /// `tracing` instead of [log].
+    ///
+    /// The `tracing` feature turns on the `log-always` feature of `tracing`, so that each of its
+    /// events is also logged as a [log] record, whether or not a `tracing` subscriber is set.
+    /// Nothing else needs to be installed: in particular `tracing-log`'s `LogTracer` goes the
+    /// other way, from [log] to `tracing`, and would take the place of this logger. Each
+    /// `tracing` level becomes the [Level] of the same name, the target stays the same, and the
+    /// fields of an event are formatted into its message. Spans are not logged.
+    ///
+    /// As for [log] itself, events are only delivered once this has been called, so it should
+    /// be called before anything that logs, and before setting any `tracing` subscriber that
+    /// should receive the events too.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::FfiLogger;
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     assert_eq!(unsafe { CStr::from_ptr(message) }, c"cache miss key=42");
+    ///     0
+    /// }
+    ///
+    /// unsafe { FfiLogger::init_with_tracing_bridge(sink, None, log::LevelFilter::Info) }.unwrap();
+    /// tracing::warn!(key = 42, "cache miss");
+    /// tracing::debug!("filtered");
+    /// ```
+    ///
+    /// # Safety
+    /// The same requirements as [FfiLogger::new] apply.
+    #[cfg(feature = "tracing")]
+    pub unsafe fn init_with_tracing_bridge(
+        logger: Callback,
+        data: Option<NonNull<c_void>>,
+        max_level: LevelFilter,
+    ) -> Result<LogHandle, SetLoggerError> {
+        Self::init(logger, data, max_level)
+    }
+
+    /// Register the logger with [log::set_logger], leaking it so that it lives for the rest of
+    /// the program, and return a handle to it.
+    ///
+    /// Unlike [FfiLogger::init] this does not change [log::set_max_level], which is
+    /// [Off](LevelFilter::Off) until it is set. This fails if a logger has already been
+    /// registered, in which case the logger is dropped and its user data still belongs to the
+    /// caller.
+    ///
+    /// ```
+    /// # use std::ffi::{c_char, c_void};
+    /// # use std::ptr::NonNull;
+    /// use ffi_logger::{FfiCallback, FfiLogger};
+    ///
+    /// # extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize { 0 }
+    /// let logger = unsafe { FfiLogger::builder(FfiCallback::Message(sink)).build() };
+    /// let handle = logger.leak_and_register().unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// log::info!("hello");
+    /// assert_eq!(handle.last_error(), None);
+    ///
+    /// let again = unsafe { FfiLogger::builder(FfiCallback::Message(sink)).build() };
+    /// assert!(again.leak_and_register().is_err());
+    /// ```
+    pub fn leak_and_register(self) -> Result<LogHandle, SetLoggerError> {
+        let logger = Box::into_raw(Box::new(self));
+
+        // Safety: The box is only reclaimed if the log crate did not keep the reference, and is
+        // otherwise never freed.
+        let logger: &'static FfiLogger = unsafe {
+            if let Err(error) = log::set_logger(&*logger) {
+                drop(Box::from_raw(logger));
+                return Err(error);
+            }
+            &*logger
+        };
+        LogHandle::register(logger);
+
+        Ok(LogHandle::new(logger))
+    }
+
+    /// Start building a logger with more options than [FfiLogger::new] provides.
+    pub fn builder(logger: FfiCallback) -> FfiLoggerBuilder {
+        FfiLoggerBuilder::new(logger)
+    }
+
+    /// Set the name of the current thread passed to the callbacks, such as for a thread made by
+    /// C code, which the standard library does not know the name of. [None] goes back to the
+    /// name the standard library knows, such as after it was set again.
+    ///
+    /// The name of each thread is only looked up the first time it logs, and kept afterwards.
+    /// It cannot be changed from within a callback, in which case this returns `false`. This
+    /// requires the `std` feature.
+    ///
+    /// ```
+    /// use std::ffi::{c_void, CStr};
+    /// use std::ptr::NonNull;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogRecord, FfiLogger};
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+    ///     let name = unsafe { CStr::from_ptr((*record).thread_name) };
+    ///     assert_eq!(name, c"decoder");
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Record(sink), None) };
+    /// log::set_boxed_logger(Box::new(logger)).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// std::thread::spawn(|| {
+    ///     assert!(FfiLogger::set_thread_name(Some("decoder")));
+    ///     log::info!("started");
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn set_thread_name(name: Option<&str>) -> bool {
+        thread::set_name(name)
     }
 
+    /// Log panics at the [Error](Level::Error) level with the target `panic`, before running
+    /// the panic hook that was set before.
+    ///
+    /// This logs through whichever logger is registered with [log], and can be undone with
+    /// [ffi::ffi_logger_set_panic_hook]. Panics while the logger is delivering a message on the
+    /// same thread, such as from a faulty [Display](fmt::Display) implementation, are not logged,
+    /// as the logger would only drop them.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use ffi_logger::FfiLogger;
+    ///
+    /// static LOGGED: AtomicBool = AtomicBool::new(false);
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     let message = unsafe { CStr::from_ptr(message) }.to_str().unwrap();
+    ///     LOGGED.store(message.ends_with(": oops"), Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// unsafe { FfiLogger::init(sink, None, log::LevelFilter::Error) }.unwrap();
+    /// FfiLogger::install_panic_hook();
+    ///
+    /// std::thread::spawn(|| panic!("oops")).join().unwrap_err();
+    /// assert!(LOGGED.load(Ordering::Relaxed));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn install_panic_hook() {
+        panic_hook::enable();
+    }
+
+    /// The most verbose level this logger passes on to the callback.
+    fn level(&self) -> LevelFilter {
+        level_filter(self.level.load(Ordering::Relaxed)).unwrap_or(LevelFilter::Trace)
+    }
+
+    /// Drop the logger, returning its user data so that it can be deallocated.
+    ///
+    /// Data owned by a logger made with [FfiLogger::with_owned_data] is dropped along with it
+    /// instead, and [None] is returned.
     pub fn into_data(self) -> Option<NonNull<c_void>> {
-        let Self { data, .. } = self;
+        let data = self
+            .dispatcher
+            .routes
+            .load()
+            .primary
+            .and_then(|route| route.data)?;
+        match &*self.owned.lock() {
+            Some(owned) if owned.data() == data => None,
+            _ => Some(data),
+        }
+    }
+}
+
+/// Creates a C string from the bytes, replacing any interior null bytes with the ASCII
+/// substitute character (`0x1A`) so that the whole message is still delivered.
+fn to_c_string(bytes: impl Into<Vec<u8>>) -> CString {
+    let mut bytes = bytes.into();
+    for byte in bytes.iter_mut().filter(|byte| **byte == 0) {
+        *byte = 0x1A;
+    }
+
+    // Safety: All null bytes were replaced above.
+    unsafe { CString::from_vec_unchecked(bytes) }
+}
+
+/// The longest message formatted by default, so that a `Display` implementation writing
+/// without end cannot use up the memory of the process.
+const DEFAULT_CEILING: usize = 1 << 20;
+
+/// The id of the process, or zero where processes have no ids.
+#[cfg(feature = "std")]
+fn process_id() -> u32 {
+    #[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+    return std::process::id();
+    #[cfg(all(target_family = "wasm", target_os = "unknown"))]
+    0
+}
+
+/// The target of the record, falling back to its module path when the target is empty.
+fn target<'a>(record: &Record<'a>) -> &'a str {
+    match record.target() {
+        "" => record.module_path().unwrap_or_default(),
+        target => target,
+    }
+}
+
+impl FfiLogger {
+    /// Format the record and pass it to each callback, unless it repeats the previous one.
+    ///
+    /// This may panic if formatting the record does, so it must not be unwound into the caller.
+    fn deliver(&self, record: &Record, sequence: u64) {
+        let header = self.header(record, sequence);
+        let Some(dedup) = &self.dedup else {
+            return self.send(record, header);
+        };
+
+        if let Some(literal) = record.args().as_str() {
+            if self.is_new(dedup, record, literal.as_bytes()) {
+                self.send(record, header);
+            }
+            return;
+        }
+        // The message is only formatted once, for both comparing and delivering it. A little
+        // more than the ceiling is kept, so that a message over it is still cut off, and
+        // counted, when the record is formatted for delivery.
+        let limit = self.ceiling.saturating_add(8);
+        buffer::with_args(*record.args(), limit, |message| {
+            if self.is_new(dedup, record, message.as_bytes()) {
+                let mut builder = Record::builder();
+                builder
+                    .metadata(record.metadata().clone())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line());
+                #[cfg(feature = "kv")]
+                builder.key_values(record.key_values());
+
+                self.send(&builder.args(format_args!("{message}")).build(), header);
+            }
+        });
+    }
+
+    /// Whether the message of the record differs from the previous one, reporting how many
+    /// times the previous one was repeated if it does.
+    fn is_new(&self, dedup: &Dedup, record: &Record, message: &[u8]) -> bool {
+        match dedup.see(record.level(), target(record), message) {
+            Seen::Repeat => false,
+            Seen::New(repeated) => {
+                if let Some((level, repeats)) = repeated {
+                    self.report_repeats(level, repeats);
+                }
+                true
+            }
+        }
+    }
+
+    /// Deliver everything that is waiting and call the flush callback, waiting at most the
+    /// timeout for the queue of an asynchronous logger if there is one.
+    ///
+    /// Returns `false` if that timed out, in which case the flush callback is not called. Unless
+    /// `force` is set, the flush callback is also skipped when no callback was called since the
+    /// last flush.
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))]
+    fn flush_within(&self, timeout: Option<Duration>, force: bool) -> bool {
+        if let Some((level, repeats)) = self.dedup.as_ref().and_then(Dedup::take_repeats) {
+            self.report_repeats(level, repeats);
+        }
+        for level in Level::iter() {
+            self.report_suppressed(level, self.rate_limits.take_suppressed(level));
+        }
+        #[cfg(feature = "std")]
+        if let Some(worker) = &self.worker {
+            if !worker.flush(timeout) {
+                return false;
+            }
+        }
+        self.dispatcher.deliver_batch();
+        if !self.dispatcher.dirty.swap(false, Ordering::Relaxed) && !force {
+            return true;
+        }
+        if let (Some(flush), Some(primary)) = (self.flush, self.dispatcher.routes.load().primary) {
+            flush(primary.data);
+        }
+        true
+    }
+
+    /// Call the function, dropping the message of the level if it panics so that the panic is
+    /// not unwound into the caller.
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))]
+    fn catch_panics(&self, level: Level, f: impl FnOnce()) {
+        #[cfg(feature = "std")]
+        if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+            self.dispatcher
+                .drop_message(level, c"formatting the message panicked");
+        }
+        // Without the standard library panics cannot be caught, and usually abort anyway.
+        #[cfg(not(feature = "std"))]
+        f();
+    }
+
+    /// Format the record and pass it to the audit callback.
+    fn audit(&self, record: &Record, sequence: u64) {
+        let header = self.header(record, sequence);
+        buffer::with_message(
+            |buffer| self.format(buffer, record, &header),
+            |message| self.dispatcher.audit(message),
+        );
+    }
+
+    /// Count a record that was filtered out, and pass it to the callback for them, if the
+    /// logger was set up to.
+    fn reject(&self, record: &Record, sequence: u64) {
+        if !self.count_rejected() {
+            return;
+        }
+        // Records logged by the callback itself are not passed back to it.
+        if let Some(_entered) = thread::enter() {
+            self.forward_rejected(record, sequence);
+        }
+    }
+
+    /// Count a record that was filtered out if they are counted, returning whether it should
+    /// also be forwarded.
+    fn count_rejected(&self) -> bool {
+        match self.dispatcher.rejected {
+            Rejected::Drop => false,
+            Rejected::Count => {
+                self.dispatcher
+                    .rejected_count
+                    .fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Rejected::Forward(..) => {
+                self.dispatcher
+                    .rejected_count
+                    .fetch_add(1, Ordering::Relaxed);
+                true
+            }
+        }
+    }
+
+    /// Format a record that was filtered out and pass it to the callback for them.
+    fn forward_rejected(&self, record: &Record, sequence: u64) {
+        self.catch_panics(record.level(), || {
+            let header = self.header(record, sequence);
+            buffer::with_message(
+                |buffer| self.format(buffer, record, &header),
+                |message| self.dispatcher.reject(message),
+            );
+        });
+    }
+
+    /// Deliver a record made by the logger itself, such as to report suppressed messages.
+    fn notify(&self, level: Level, args: fmt::Arguments) {
+        let record = Record::builder()
+            .level(level)
+            .target("ffi_logger")
+            .args(args)
+            .build();
+
+        self.send(&record, self.header(&record, 0));
+    }
+
+    fn report_repeats(&self, level: Level, repeats: u64) {
+        self.notify(level, format_args!("last message repeated {repeats} times"));
+    }
+
+    fn report_suppressed(&self, level: Level, suppressed: u64) {
+        if suppressed > 0 {
+            self.notify(level, format_args!("suppressed {suppressed} messages"));
+        }
+    }
+
+    fn header(&self, record: &Record, sequence: u64) -> Header {
+        let (thread_id, thread_name) = thread::current();
+
+        Header {
+            level: record.level(),
+            line: record.line().unwrap_or(0),
+            timestamp: if self.timestamps.is_some() || self.json() {
+                clock::unix_nanos()
+            } else {
+                0
+            },
+            sequence,
+            thread_id,
+            thread_name,
+            #[cfg(feature = "kv")]
+            kv_count: record.key_values().count(),
+            #[cfg(not(feature = "kv"))]
+            kv_count: 0,
+            component: self.component.map_or(ptr::null(), CStr::as_ptr),
+            #[cfg(feature = "std")]
+            thread_data: self
+                .thread_data
+                .then(|| thread::data(self.thread_key()))
+                .flatten(),
+            #[cfg(not(feature = "std"))]
+            thread_data: None,
+            process_id: self.dispatcher.process_id,
+        }
+    }
+
+    /// Format the record and pass it to each callback, or to the worker thread.
+    fn send(&self, record: &Record, header: Header) {
+        if self.dispatcher.is_unavailable(header.level) {
+            // The recent messages are kept even while there is nowhere to deliver them.
+            if self.recent.is_some() {
+                buffer::with_message(
+                    |buffer| self.format(buffer, record, &header),
+                    |message| self.remember(message.raw()),
+                );
+            }
+            #[cfg(feature = "std")]
+            if self.stderr_fallback.load(Ordering::Relaxed) && !self.dispatcher.has_routes() {
+                // This does not allocate, and failing to write is ignored.
+                let _ = writeln!(
+                    io::stderr(),
+                    "{} {}: {}",
+                    self.level_name(record.level()),
+                    target(record),
+                    record.args()
+                );
+            }
+            return;
+        }
+        self.level_counts[level_index(header.level)].fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(feature = "kv")]
+        if let (Some(callback), Some(primary)) = (self.kv, self.dispatcher.routes.load().primary) {
+            kv::forward(callback, primary.data, record);
+        }
+
+        #[cfg(feature = "std")]
+        if let Some(worker) = &self.worker {
+            let mut message = Vec::new();
+            self.format(&mut message, record, &header);
+            self.remember(&message);
+            message.push(0);
+
+            let queued = Queued::new(header, RecordStrings::new(record), message);
+            // Once the worker is stopped, records are delivered on the thread logging them.
+            if let Some(queued) = worker.push(queued) {
+                queued.deliver(&self.dispatcher);
+            }
+            return;
+        }
+
+        // A message without any formatting is passed on from where it is, when nothing is added
+        // to it and it is never needed as a C string.
+        if let Some(literal) = self.literal(record) {
+            if self.dispatcher.only_raw(header.level) {
+                self.remember(literal.as_bytes());
+                #[cfg(all(feature = "kv", feature = "std"))]
+                let _current = kv::set_current(record);
+                let mut message = buffer::Message::borrowed(literal.as_bytes());
+                self.dispatcher
+                    .dispatch(&header, || RecordStrings::new(record), &mut message);
+                return;
+            }
+        }
+
+        buffer::with_message(
+            |buffer| self.format(buffer, record, &header),
+            |message| {
+                self.remember(message.raw());
+                #[cfg(all(feature = "kv", feature = "std"))]
+                let _current = kv::set_current(record);
+                self.dispatcher
+                    .dispatch(&header, || RecordStrings::new(record), message)
+            },
+        );
+    }
 
-        data
+    /// The message of the record, if it is a string without any formatting that is delivered
+    /// exactly as it is.
+    fn literal(&self, record: &Record) -> Option<&'static str> {
+        let literal = record.args().as_str()?;
+        let unchanged = self.formatter.is_none()
+            && !self.json()
+            && self.timestamps != Some(TimestampFormat::Rfc3339)
+            && !self.ansi_colors
+            && !self.newline
+            && self.context.read().is_none()
+            && literal.len() <= self.limit();
+
+        unchanged.then_some(literal)
+    }
+
+    /// Keep the formatted message as one of the recent messages, if they are kept.
+    fn remember(&self, message: &[u8]) {
+        if let Some(recent) = &self.recent {
+            // The recent messages are separated by newlines when copied anyway.
+            let message = match self.newline {
+                true => message.strip_suffix(b"\n").unwrap_or(message),
+                false => message,
+            };
+            recent.push(message);
+        }
+    }
+
+    /// Write the message delivered for the record into the buffer.
+    fn format(&self, buffer: &mut Vec<u8>, record: &Record, header: &Header) {
+        if let Some(size) = self.buffer_size {
+            // A formatter writes after the message passed to it, which both fit in a fixed buffer.
+            let size = if self.formatter.is_some() {
+                size * 2
+            } else {
+                size
+            };
+            // This only allocates the first time, as the buffer of the thread is kept.
+            buffer.reserve(size.saturating_sub(buffer.len()));
+        }
+        let formatted = self.formatter.and_then(|formatter| {
+            let interior_nul = self.dispatcher.interior_nul;
+            let room = self.fixed_room;
+            formatter.write(buffer, record, header, self.ceiling, room, interior_nul)
+        });
+        match formatted {
+            // Only the ceiling and a fixed buffer apply to the message passed to a formatter.
+            Some(formatted) => {
+                let fixed = self.fixed_room.is_some_and(|room| room < self.ceiling);
+                if formatted.output_cut || (formatted.message_cut && fixed) {
+                    self.dispatcher.truncated.fetch_add(1, Ordering::Relaxed);
+                } else if formatted.message_cut {
+                    self.dispatcher.capped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            None => {
+                if self.write_message(buffer, record, header.timestamp) {
+                    if self.max_len.is_some_and(|max_len| max_len <= self.ceiling) {
+                        self.dispatcher.truncated.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        self.dispatcher.capped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        if self.newline {
+            buffer.push(b'\n');
+        }
+    }
+
+    /// Write the message for the record into the buffer, returning whether it was cut off.
+    #[cfg_attr(not(feature = "json"), allow(unused_variables))]
+    fn write_message(&self, buffer: &mut Vec<u8>, record: &Record, timestamp: u64) -> bool {
+        let context = self.context.read().clone();
+
+        #[cfg(feature = "json")]
+        if self.json {
+            return json::write_record(
+                buffer,
+                record,
+                &self.level_name(record.level()),
+                timestamp,
+                self.ansi_colors,
+                context.as_deref(),
+                Some(self.limit()),
+            );
+        }
+
+        let start = buffer.len();
+        if self.timestamps == Some(TimestampFormat::Rfc3339) {
+            let _ = clock::write_rfc3339(&mut Writer(buffer), timestamp);
+            buffer.extend_from_slice(self.separator.to_bytes());
+        }
+        if self.ansi_colors {
+            let level = record.level();
+            buffer.extend_from_slice(color::sgr(level).as_bytes());
+            buffer.extend_from_slice(self.level_names[level_index(level)].to_bytes());
+            buffer.extend_from_slice(color::RESET.as_bytes());
+            buffer.extend_from_slice(self.separator.to_bytes());
+        }
+        if let Some(context) = context {
+            let _ = write!(Writer(buffer), "{context}:");
+            buffer.extend_from_slice(self.separator.to_bytes());
+        }
+
+        buffer::write_limited(buffer, start, *record.args(), Some(self.limit()))
+    }
+
+    /// What identifies the logger among those the user data of a thread may be set for.
+    #[cfg(feature = "std")]
+    fn thread_key(&self) -> usize {
+        Arc::as_ptr(&self.dispatcher) as usize
+    }
+
+    /// The name of the level, replacing anything that is not UTF-8.
+    #[cfg(any(feature = "std", feature = "json"))]
+    fn level_name(&self, level: Level) -> Cow<'static, str> {
+        self.level_names[level_index(level)].to_string_lossy()
+    }
+
+    /// The longest message that is formatted, which is the smaller of `max_len`, if there is
+    /// one, and the ceiling.
+    fn limit(&self) -> usize {
+        self.max_len
+            .map_or(self.ceiling, |max_len| max_len.min(self.ceiling))
+    }
+
+    fn json(&self) -> bool {
+        #[cfg(feature = "json")]
+        return self.json;
+        #[cfg(not(feature = "json"))]
+        false
     }
 }
 
+impl Log for FfiLogger {
+    /// Whether a record with the metadata is passed on, by the level, the target, the level
+    /// mask, whether the logger is enabled, and the filter callback.
+    ///
+    /// [Log::log] decides by this alone before formatting anything, so a record is never
+    /// formatted only to be dropped by these. Records can still be dropped later by rate limits
+    /// or deduplication, which depend on more than the metadata.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void};
+    /// use std::ptr::NonNull;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle};
+    /// use log::{Level, LevelFilter, Log, Metadata, Record};
+    ///
+    /// static DELIVERED: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// extern "C" fn sink(_: Option<NonNull<c_void>>, _: *const c_char) -> isize {
+    ///     DELIVERED.fetch_add(1, Ordering::Relaxed);
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe { FfiLogger::with_callback(FfiCallback::Message(sink), None) };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// let handle = LogHandle::new(logger);
+    /// handle.set_level(LevelFilter::Info);
+    /// handle.set_level_mask(0b1_1101);
+    /// handle.set_target_level("noisy", LevelFilter::Error);
+    /// handle.set_target_denylist(&["secret"]);
+    ///
+    /// for enabled in [true, false] {
+    ///     handle.set_enabled(enabled);
+    ///     for level in [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace] {
+    ///         for target in ["app", "noisy", "secret"] {
+    ///             let metadata = Metadata::builder().level(level).target(target).build();
+    ///             let before = DELIVERED.load(Ordering::Relaxed);
+    ///             let record = Record::builder()
+    ///                 .metadata(metadata.clone())
+    ///                 .args(format_args!("message"))
+    ///                 .build();
+    ///             logger.log(&record);
+    ///
+    ///             let delivered = DELIVERED.load(Ordering::Relaxed) > before;
+    ///             assert_eq!(logger.enabled(&metadata), delivered, "{level} {target}");
+    ///         }
+    ///     }
+    /// }
+    /// // `app` at Error and Info, and `noisy` at Error.
+    /// assert_eq!(DELIVERED.load(Ordering::Relaxed), 3);
+    /// ```
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if !self.on.load(Ordering::Relaxed) {
+            return false;
+        }
+        let level = self
+            .target_levels
+            .get(metadata.target())
+            .unwrap_or_else(|| self.level());
+
+        metadata.level() <= level
+            && self.level_mask.load(Ordering::Relaxed) & level_bit(metadata.level()) != 0
+            && self.target_lists.allows(metadata.target())
+            && self.filter.is_none_or(|filter| {
+                let data = self
+                    .dispatcher
+                    .routes
+                    .load()
+                    .primary
+                    .and_then(|route| route.data);
+                let target = metadata.target();
+                filter(data, metadata.level().into(), target.as_ptr(), target.len())
+            })
+    }
+
+    fn log(&self, record: &Record) {
+        self.poison.check();
+        // Muting does not apply to the audit callback, which is passed every record.
+        let on = self.on.load(Ordering::Relaxed);
+        let audit = self.dispatcher.has_audit();
+        if !on && !audit {
+            return;
+        }
+        // Records are numbered before filtering, so that filtered records leave a gap.
+        let sequence = self
+            .sequence
+            .as_ref()
+            .map_or(0, |sequence| sequence.fetch_add(1, Ordering::Relaxed));
+        // This is decided once for the whole record, so that it always agrees with `enabled`.
+        let enabled = self.enabled(record.metadata());
+        if !audit && !enabled {
+            self.reject(record, sequence);
+            return;
+        }
+        let Some(_entered) = thread::enter() else {
+            self.dispatcher
+                .drop_message(record.level(), c"logged from within the logger");
+            return;
+        };
+
+        if audit {
+            self.catch_panics(record.level(), || self.audit(record, sequence));
+            if !enabled {
+                if on && self.count_rejected() {
+                    self.forward_rejected(record, sequence);
+                }
+                return;
+            }
+        }
+        if !self.sampling.keep(record.level()) {
+            return;
+        }
+        let Some(suppressed) = self.rate_limits.acquire(record.level()) else {
+            return;
+        };
+
+        let deliver = || {
+            self.report_suppressed(record.level(), suppressed);
+            self.deliver(record, sequence);
+        };
+        self.catch_panics(record.level(), deliver);
+    }
+
+    fn flush(&self) {
+        self.flush_within(None, false);
+    }
+}
+
+#[cfg(feature = "std")]
 impl Write for FfiLogger {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(Route {
+            logger: FfiCallback::Message(logger),
+            data,
+        }) = self.dispatcher.routes.load().primary
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only message callbacks can be written to",
+            ));
+        };
+
         let c_str = CString::new(buf)?;
 
-        let written = (self.logger)(self.data, c_str.as_ptr());
+        let written = logger(data, c_str.as_ptr());
+        self.dispatcher.dirty.store(true, Ordering::Relaxed);
 
         match written.try_into() {
             // If it suceeds, that means some non-negative value was returned.
             Ok(written) => Ok(written),
 
             // If it fails, then it is negative so provide the error code.
-            Err(_) => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("FFI logging error: {written}"),
-            )),
+            Err(_) => Err(io::Error::other(format!("FFI logging error: {written}"))),
         }
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
+    fn flush(&mut self) -> io::Result<()> {
+        Log::flush(self);
         Ok(())
     }
 }
@@ -0,0 +1,115 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::ffi::CString;
+use core::ffi::{c_char, c_void, CStr};
+use core::ptr::NonNull;
+
+use crate::sync::Mutex;
+use crate::{to_c_string, FfiCallback, FfiLevel, FfiLogRecord, FfiLogger, FfiLoggerBuilder};
+
+extern "C" {
+    fn os_log_create(subsystem: *const c_char, category: *const c_char) -> *mut c_void;
+}
+
+/// A function writing a message to an `os_log_t` with an `os_log_type_t`.
+///
+/// `os_log` itself is a macro, so this has to be written in C or Objective-C, such as:
+///
+/// ```c
+/// #include <os/log.h>
+///
+/// void write_os_log(os_log_t log, os_log_type_t type, const char *message) {
+///     os_log_with_type(log, type, "%{public}s", message);
+/// }
+/// ```
+pub type OsLogWrite = unsafe extern "C" fn(*mut c_void, u8, *const c_char);
+
+impl FfiLevel {
+    /// The `os_log_type_t` of the level, as passed to [OsLogWrite].
+    ///
+    /// | `FfiLevel` | Type                           |
+    /// |------------|--------------------------------|
+    /// | `Error`    | `0x10` (`OS_LOG_TYPE_ERROR`)   |
+    /// | `Warn`     | `0x00` (`OS_LOG_TYPE_DEFAULT`) |
+    /// | `Info`     | `0x01` (`OS_LOG_TYPE_INFO`)    |
+    /// | `Debug`    | `0x02` (`OS_LOG_TYPE_DEBUG`)   |
+    /// | `Trace`    | `0x02` (`OS_LOG_TYPE_DEBUG`)   |
+    ///
+    /// `OS_LOG_TYPE_FAULT` is not used, as it is meant for bugs in the system rather than the
+    /// program.
+    pub const fn os_log_type(self) -> u8 {
+        match self {
+            FfiLevel::Error => 0x10,
+            FfiLevel::Warn => 0x00,
+            FfiLevel::Info => 0x01,
+            FfiLevel::Debug | FfiLevel::Trace => 0x02,
+        }
+    }
+}
+
+/// The subsystem and the `os_log_t` made for each target, which is the category.
+struct OsLog {
+    subsystem: CString,
+    write: OsLogWrite,
+    logs: Mutex<BTreeMap<CString, *mut c_void>>,
+}
+
+// Safety: `os_log_t` objects can be used from any thread, and are only accessed while locked.
+unsafe impl Send for OsLog {}
+unsafe impl Sync for OsLog {}
+
+impl FfiLogger {
+    /// Start building a logger that writes to `os_log` through `write`, with the subsystem and
+    /// the target of each record as its category. Its level is mapped with
+    /// [FfiLevel::os_log_type].
+    ///
+    /// The subsystem and the logs made for it are passed as the user data, so
+    /// [FfiLoggerBuilder::data] must not be changed. They are never freed, like the `os_log_t`
+    /// objects themselves.
+    ///
+    /// ```no_run
+    /// use std::ffi::{c_char, c_void};
+    ///
+    /// use ffi_logger::FfiLogger;
+    ///
+    /// extern "C" {
+    ///     fn write_os_log(log: *mut c_void, kind: u8, message: *const c_char);
+    /// }
+    ///
+    /// // Safety: `os_log` can be called from any thread.
+    /// let logger = unsafe { FfiLogger::with_os_log("com.example.app", write_os_log).build() };
+    /// logger.leak_and_register().unwrap();
+    /// ```
+    pub fn with_os_log(subsystem: &str, write: OsLogWrite) -> FfiLoggerBuilder {
+        let os_log = Box::leak(Box::new(OsLog {
+            subsystem: to_c_string(subsystem),
+            write,
+            logs: Mutex::new(BTreeMap::new()),
+        }));
+        let data = Some(NonNull::from(os_log).cast());
+        Self::builder(FfiCallback::Record(trampoline)).data(data)
+    }
+}
+
+/// Writes the record to the log for its target, making the log the first time the target is
+/// seen.
+extern "C" fn trampoline(data: Option<NonNull<c_void>>, record: *const FfiLogRecord) -> isize {
+    let (Some(data), Some(record)) = (data, unsafe { record.as_ref() }) else {
+        return 0;
+    };
+    // Safety: The data is only ever a leaked `OsLog` from `FfiLogger::with_os_log`.
+    let os_log = unsafe { data.cast::<OsLog>().as_ref() };
+    // Safety: The strings of the record are valid for the duration of the call.
+    let target = unsafe { CStr::from_ptr(record.target) };
+
+    let log = *os_log
+        .logs
+        .lock()
+        .entry(target.into())
+        // Safety: Both strings are null-terminated.
+        .or_insert_with(|| unsafe { os_log_create(os_log.subsystem.as_ptr(), target.as_ptr()) });
+
+    // Safety: The log came from `os_log_create`, and the message is valid for the call.
+    unsafe { (os_log.write)(log, record.level.os_log_type(), record.message) };
+    record.message_len as isize
+}
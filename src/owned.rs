@@ -0,0 +1,48 @@
+use alloc::boxed::Box;
+use core::ffi::c_void;
+use core::fmt;
+use core::ptr::NonNull;
+
+/// User data owned by the logger, which is dropped by [LogHandle::deinit](crate::LogHandle::deinit)
+/// or along with the logger.
+pub(crate) struct OwnedData {
+    data: NonNull<c_void>,
+    /// Drops the box the data came from, as its type is erased.
+    drop: unsafe fn(NonNull<c_void>),
+}
+
+// Safety: The data is required to be thread-safe, and is only freed on drop.
+unsafe impl Send for OwnedData {}
+unsafe impl Sync for OwnedData {}
+
+impl OwnedData {
+    pub(crate) fn new<T: Send + Sync + 'static>(data: Box<T>) -> OwnedData {
+        /// Safety: The data must have come from `Box<T>`, and not be used anymore.
+        unsafe fn drop_box<T>(data: NonNull<c_void>) {
+            drop(Box::from_raw(data.cast::<T>().as_ptr()));
+        }
+
+        Self {
+            data: NonNull::from(Box::leak(data)).cast(),
+            drop: drop_box::<T>,
+        }
+    }
+
+    pub(crate) fn data(&self) -> NonNull<c_void> {
+        self.data
+    }
+}
+
+impl Drop for OwnedData {
+    fn drop(&mut self) {
+        // Safety: The data came from the box `drop` was made for, and the logger owning this no
+        // longer passes it to the callbacks.
+        unsafe { (self.drop)(self.data) }
+    }
+}
+
+impl fmt::Debug for OwnedData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OwnedData").field(&self.data).finish()
+    }
+}
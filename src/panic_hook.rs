@@ -0,0 +1,60 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::panic::{self, PanicHookInfo};
+use std::sync::Once;
+
+use log::{Level, Record};
+
+use crate::thread;
+
+/// Whether panics are logged by the hook, which stays installed once it has been.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static INSTALL: Once = Once::new();
+
+/// Start logging panics, installing the hook the first time.
+pub(crate) fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            log_panic(info);
+            previous(info);
+        }));
+    });
+}
+
+/// Stop logging panics, leaving only the hook that was installed before.
+pub(crate) fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+fn log_panic(info: &PanicHookInfo) {
+    // A panic while logging would only be dropped by the logger, as it is not reentrant.
+    if !ENABLED.load(Ordering::Relaxed) || thread::is_logging() || Level::Error > log::max_level() {
+        return;
+    }
+
+    let payload = info.payload();
+    let message = match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message.as_str(),
+        _ => "Box<dyn Any>",
+    };
+
+    let mut record = Record::builder();
+    record.level(Level::Error).target("panic");
+    match info.location() {
+        Some(location) => log::logger().log(
+            &record
+                .file(Some(location.file()))
+                .line(Some(location.line()))
+                .args(format_args!("panicked at {location}: {message}"))
+                .build(),
+        ),
+        None => log::logger().log(&record.args(format_args!("panicked: {message}")).build()),
+    }
+}
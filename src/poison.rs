@@ -0,0 +1,39 @@
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Whether the logger was deinitialized without being given a callback again, and how many
+/// records were logged to it since.
+///
+/// This holds nothing in release builds, where every check compiles away.
+#[derive(Debug, Default)]
+pub(crate) struct Poison {
+    #[cfg(debug_assertions)]
+    poisoned: AtomicBool,
+    #[cfg(debug_assertions)]
+    uses: AtomicUsize,
+}
+
+impl Poison {
+    /// Poison the logger, or clear the poison once it has a callback again.
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    pub(crate) fn set(&self, poisoned: bool) {
+        #[cfg(debug_assertions)]
+        self.poisoned.store(poisoned, Ordering::Relaxed);
+    }
+
+    /// Count a use of the logger if it is poisoned.
+    pub(crate) fn check(&self) {
+        #[cfg(debug_assertions)]
+        if self.poisoned.load(Ordering::Relaxed) {
+            self.uses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The number of uses counted while the logger was poisoned.
+    pub(crate) fn uses(&self) -> usize {
+        #[cfg(debug_assertions)]
+        return self.uses.load(Ordering::Relaxed);
+        #[cfg(not(debug_assertions))]
+        0
+    }
+}
@@ -0,0 +1,100 @@
+use log::Level;
+
+use crate::clock;
+use crate::level::level_index;
+use crate::sync::Mutex;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Token buckets limiting how many records of each level are delivered per second.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimits {
+    /// The bucket of each level, indexed by its discriminant minus one.
+    buckets: [Option<Bucket>; 5],
+}
+
+impl RateLimits {
+    /// Limit the level to the number of records per second.
+    pub(crate) fn set(&mut self, level: Level, per_second: u32) {
+        self.buckets[level_index(level)] = Some(Bucket::new(per_second));
+    }
+
+    /// Take a token for a record of the level.
+    ///
+    /// Returns [None] if the record should be suppressed. Otherwise it should be delivered, and
+    /// the number of records of the level suppressed since the last delivered one is returned.
+    pub(crate) fn acquire(&self, level: Level) -> Option<u64> {
+        match &self.buckets[level_index(level)] {
+            Some(bucket) => bucket.acquire(),
+            None => Some(0),
+        }
+    }
+
+    /// Take the number of records of the level suppressed since the last delivered one, so that
+    /// it can be reported without waiting for another record to be delivered.
+    pub(crate) fn take_suppressed(&self, level: Level) -> u64 {
+        self.buckets[level_index(level)]
+            .as_ref()
+            .map_or(0, |bucket| {
+                core::mem::take(&mut bucket.state.lock().suppressed)
+            })
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// The most tokens that can be held, which is also the number refilled each second.
+    capacity: u32,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    tokens: u32,
+    /// The [clock::monotonic_nanos] at which tokens were last refilled.
+    refilled_at: u64,
+    suppressed: u64,
+}
+
+impl Bucket {
+    fn new(capacity: u32) -> Bucket {
+        Self {
+            capacity,
+            state: Mutex::new(State {
+                tokens: capacity,
+                refilled_at: clock::monotonic_nanos().unwrap_or(0),
+                suppressed: 0,
+            }),
+        }
+    }
+
+    fn acquire(&self) -> Option<u64> {
+        // Without a clock tokens could never be refilled, so nothing is limited.
+        let Some(now) = clock::monotonic_nanos() else {
+            return Some(0);
+        };
+        let mut state = self.state.lock();
+
+        if self.capacity > 0 {
+            let interval = (NANOS_PER_SECOND / u64::from(self.capacity)).max(1);
+            let refills = now.saturating_sub(state.refilled_at) / interval;
+            let tokens = u64::from(state.tokens) + refills;
+
+            if tokens >= u64::from(self.capacity) {
+                state.tokens = self.capacity;
+                state.refilled_at = now;
+            } else {
+                state.tokens = tokens as u32;
+                state.refilled_at += refills * interval;
+            }
+        }
+
+        if state.tokens == 0 {
+            state.suppressed += 1;
+            return None;
+        }
+        state.tokens -= 1;
+
+        Some(core::mem::take(&mut state.suppressed))
+    }
+}
@@ -0,0 +1,65 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::sync::Mutex;
+
+/// The most recent messages, kept so that they can be retrieved later, such as for a crash
+/// report.
+#[derive(Debug)]
+pub(crate) struct Recent {
+    messages: Mutex<VecDeque<String>>,
+    capacity: usize,
+}
+
+impl Recent {
+    pub(crate) fn new(capacity: usize) -> Recent {
+        Self {
+            messages: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Keep the message, forgetting the oldest one if there are already as many as fit.
+    pub(crate) fn push(&self, message: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let message = String::from_utf8_lossy(message).into_owned();
+        let mut messages = self.messages.lock();
+        if messages.len() == self.capacity {
+            messages.pop_front();
+        }
+        messages.push_back(message);
+    }
+
+    /// Take the kept messages, from oldest to newest.
+    pub(crate) fn drain(&self) -> Vec<String> {
+        self.messages.lock().drain(..).collect()
+    }
+
+    /// Copy the kept messages into the buffer, each followed by a newline, and null-terminate
+    /// it. Returns the length of all of the messages, which is more than was copied if the
+    /// buffer is too small.
+    pub(crate) fn copy_to(&self, buffer: &mut [u8]) -> usize {
+        let messages = self.messages.lock();
+        let mut len = 0;
+        // Keep room for the null terminator.
+        let room = buffer.len().saturating_sub(1);
+
+        for message in messages.iter() {
+            for &byte in message.as_bytes().iter().chain(b"\n") {
+                if len < room {
+                    buffer[len] = byte;
+                }
+                len += 1;
+            }
+        }
+        if let Some(end) = buffer.get_mut(len.min(room)) {
+            *end = 0;
+        }
+
+        len
+    }
+}
@@ -0,0 +1,90 @@
+use core::ffi::c_char;
+use core::mem;
+
+use crate::FfiLevel;
+
+/// The [FfiLogRecord::version] of the records passed by this version of the crate.
+pub const FFI_LOG_RECORD_VERSION: u32 = 4;
+
+/// A record passed to a [RecordCallback](crate::RecordCallback), with the message and all of its
+/// metadata.
+///
+/// New fields are only ever added at the end, so C code compiled against an older version of
+/// this struct can keep reading the fields it knows about. Use [FfiLogRecord::size] to check
+/// whether a newer field is present before reading it. A C host can declare this as:
+///
+/// ```c
+/// typedef struct {
+///     uint32_t size;
+///     uint32_t version;
+///     FfiLevel level;
+///     const char* message;
+///     size_t message_len;
+///     const char* target;
+///     const char* module_path;
+///     const char* file;
+///     uint32_t line;
+///     uint64_t timestamp;
+///     uint64_t sequence;
+///     uint64_t thread_id;
+///     const char* thread_name;
+///     size_t kv_count;
+///     const char* component;
+///     uint32_t process_id;
+/// } FfiLogRecord;
+/// ```
+///
+/// All strings are null-terminated, and are only valid for the duration of the call.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FfiLogRecord {
+    /// The size of this struct in bytes, as known to this crate.
+    pub size: u32,
+    /// The version of this struct, which is [FFI_LOG_RECORD_VERSION].
+    pub version: u32,
+    pub level: FfiLevel,
+    pub message: *const c_char,
+    /// The length of the message in bytes, not including the null terminator.
+    pub message_len: usize,
+    /// The target of the record. When the record has an empty target this falls back to the
+    /// module path, or to an empty string, so it is never null.
+    pub target: *const c_char,
+    /// The module path of the record, or null if unavailable.
+    pub module_path: *const c_char,
+    /// The source file of the record, or null if unavailable.
+    pub file: *const c_char,
+    /// The source line of the record, or `0` if unavailable.
+    pub line: u32,
+    /// The time the record was logged, in nanoseconds since the Unix epoch as measured by the
+    /// system clock, or `0` if timestamps were not enabled with
+    /// [FfiLoggerBuilder::timestamps](crate::FfiLoggerBuilder::timestamps). This is always `0`
+    /// on `wasm32-unknown-unknown`, which has no system clock.
+    pub timestamp: u64,
+    /// The number of the record, or `0` if numbering was not enabled with
+    /// [FfiLoggerBuilder::sequence_numbers](crate::FfiLoggerBuilder::sequence_numbers).
+    pub sequence: u64,
+    /// An id of the thread that logged the record, or `0` if unavailable. Ids are assigned by
+    /// this crate and are unique for the lifetime of the process, but are unrelated to the ids
+    /// of the operating system.
+    pub thread_id: u64,
+    /// The name of the thread that logged the record, or null if it is unnamed.
+    pub thread_name: *const c_char,
+    /// The number of structured key-value pairs of the record, which is always `0` without the
+    /// `kv` feature. Added in version 2.
+    ///
+    /// While a record is being delivered on the thread that logged it, its pairs can be read
+    /// with [ffi_logger_current_key_values](crate::ffi::ffi_logger_current_key_values), so that
+    /// they are only formatted when needed.
+    pub kv_count: usize,
+    /// The component set with
+    /// [FfiLoggerBuilder::component](crate::FfiLoggerBuilder::component), or null if there is
+    /// none. Unlike the other strings this is valid for the lifetime of the program. Added in
+    /// version 3.
+    pub component: *const c_char,
+    /// The id of the process, or `0` if it was not enabled with
+    /// [FfiLoggerBuilder::process_id](crate::FfiLoggerBuilder::process_id). Added in version 4.
+    pub process_id: u32,
+}
+
+/// The size of [FfiLogRecord], which is far below `u32::MAX`.
+pub(crate) const SIZE: u32 = mem::size_of::<FfiLogRecord>() as u32;
@@ -0,0 +1,222 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use crate::sync::Mutex;
+use crate::{FfiCallback, FfiLevel};
+
+/// A callback along with the user data passed to it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Route {
+    pub(crate) logger: FfiCallback,
+    pub(crate) data: Option<NonNull<c_void>>,
+}
+
+impl Route {
+    /// Tell the callback that the logger is shutting down, by calling it with a null message.
+    ///
+    /// For [FfiCallback::Record] the record pointer itself is null, for [FfiCallback::Batch]
+    /// the records are null with a count of zero, and the callbacks receiving
+    /// a level or severity are passed that of [FfiLevel::Info] and a length of zero.
+    /// [FfiCallback::Context] callbacks are passed the context as usual.
+    pub(crate) fn shut_down(self, context: Option<NonNull<c_void>>) {
+        let _ = match self.logger {
+            FfiCallback::Message(logger) => logger(self.data, ptr::null()),
+            FfiCallback::Context(logger) => logger(self.data, context, ptr::null()),
+            FfiCallback::Record(logger) => logger(self.data, ptr::null()),
+            FfiCallback::Id(logger, id) => logger(id, ptr::null()),
+            FfiCallback::Owned(logger) => logger(self.data, ptr::null_mut()),
+            FfiCallback::Batch(logger) => logger(self.data, ptr::null(), 0),
+            FfiCallback::Buffer(logger) => logger(self.data, FfiLevel::Info, ptr::null(), 0),
+            FfiCallback::Raw(logger) => logger(self.data, FfiLevel::Info, ptr::null(), 0),
+            #[cfg(windows)]
+            FfiCallback::Wide(logger) => logger(self.data, FfiLevel::Info, ptr::null(), 0),
+            FfiCallback::Syslog(logger) => logger(
+                self.data,
+                FfiLevel::Info.syslog_severity().into(),
+                ptr::null(),
+                0,
+            ),
+        };
+    }
+}
+
+/// Identifies a sink added with [LogHandle::add_sink](crate::LogHandle::add_sink).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SinkId(u64);
+
+/// Every route messages are delivered through.
+#[derive(Debug, Clone)]
+pub(crate) struct Routes {
+    /// The route the logger was created with.
+    pub(crate) primary: Option<Route>,
+    pub(crate) sinks: Vec<(SinkId, Route)>,
+    next_id: u64,
+}
+
+impl Routes {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Route> {
+        self.primary
+            .iter()
+            .chain(self.sinks.iter().map(|(_, route)| route))
+    }
+
+    /// The routes added besides the primary one.
+    pub(crate) fn sinks(&self) -> impl Iterator<Item = Route> + '_ {
+        self.sinks.iter().map(|&(_, route)| route)
+    }
+
+    pub(crate) fn add(&mut self, route: Route) -> SinkId {
+        let id = SinkId(self.next_id);
+        self.next_id += 1;
+        self.sinks.push((id, route));
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: SinkId) -> Option<Route> {
+        let index = self.sinks.iter().position(|(sink, _)| *sink == id)?;
+        Some(self.sinks.remove(index).1)
+    }
+}
+
+/// [Routes] that can be changed while other threads are delivering through them.
+///
+/// Changes are made to a copy which then replaces the current routes, so that a callback is
+/// never passed the data of another. Replaced routes are kept until no thread is using any routes
+/// anymore, which is checked on each change and whenever the last thread using them is done.
+#[derive(Debug)]
+pub(crate) struct SharedRoutes {
+    current: AtomicPtr<Routes>,
+    /// Whether the current routes have any route, so that it can be checked without loading them.
+    active: AtomicBool,
+    /// How many [RoutesGuard]s there are.
+    readers: AtomicUsize,
+    /// Whether there are replaced routes waiting to be freed.
+    pending: AtomicBool,
+    // The routes must stay at the same address while other threads may be using them. This
+    // is also locked while changing the routes, so that concurrent changes are not lost.
+    #[allow(clippy::vec_box)]
+    retired: Mutex<Vec<Box<Routes>>>,
+}
+
+impl SharedRoutes {
+    pub(crate) fn new(primary: Route) -> SharedRoutes {
+        let routes = Routes {
+            primary: Some(primary),
+            sinks: Vec::new(),
+            next_id: 0,
+        };
+
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(routes))),
+            active: AtomicBool::new(true),
+            readers: AtomicUsize::new(0),
+            pending: AtomicBool::new(false),
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The current routes, which are kept for as long as the guard exists.
+    pub(crate) fn load(&self) -> RoutesGuard<'_> {
+        self.enter(true)
+    }
+
+    /// The current routes, without freeing replaced ones when the guard is dropped, which
+    /// would not be async-signal-safe.
+    pub(crate) fn load_signal_safe(&self) -> RoutesGuard<'_> {
+        self.enter(false)
+    }
+
+    /// Whether the current routes have any route, with a single atomic load.
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    fn enter(&self, reclaim: bool) -> RoutesGuard<'_> {
+        // Every access to `readers` and `current` is sequentially consistent, so that a thread
+        // freeing the routes that sees no readers also sees that the routes were replaced before
+        // any reader could have loaded them.
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        // Safety: Replaced routes are only freed while there are no guards, and this thread
+        // is counted as a reader before loading the pointer.
+        let routes = unsafe { &*self.current.load(Ordering::SeqCst) };
+
+        RoutesGuard {
+            shared: self,
+            routes,
+            reclaim,
+        }
+    }
+
+    /// Change a copy of the current routes, and then make it the current routes.
+    pub(crate) fn update<R>(&self, f: impl FnOnce(&mut Routes) -> R) -> R {
+        let mut retired = self.retired.lock();
+
+        let mut routes = self.load().clone();
+        let result = f(&mut routes);
+        self.active
+            .store(routes.iter().next().is_some(), Ordering::Release);
+
+        // The swap makes the new routes, and whatever their callbacks' data points to, visible
+        // to any thread whose `load` observes the new pointer.
+        let old = self
+            .current
+            .swap(Box::into_raw(Box::new(routes)), Ordering::SeqCst);
+        // Safety: The pointer came from `Box::into_raw`, and was just swapped out so it cannot
+        // be retired twice.
+        retired.push(unsafe { Box::from_raw(old) });
+        self.pending.store(true, Ordering::SeqCst);
+        self.reclaim(&mut retired);
+
+        result
+    }
+
+    /// Free the replaced routes if no thread can be using them anymore.
+    #[allow(clippy::vec_box)]
+    fn reclaim(&self, retired: &mut Vec<Box<Routes>>) {
+        // Any reader that loaded replaced routes was counted before they were replaced, which
+        // happened before they were retired with the lock held.
+        if self.readers.load(Ordering::SeqCst) == 0 {
+            retired.clear();
+            self.pending.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for SharedRoutes {
+    fn drop(&mut self) {
+        // Safety: The pointer came from `Box::into_raw`, and nothing else can use it anymore.
+        drop(unsafe { Box::from_raw(*self.current.get_mut()) });
+    }
+}
+
+/// Routes loaded from [SharedRoutes], which are not freed while this exists.
+pub(crate) struct RoutesGuard<'a> {
+    shared: &'a SharedRoutes,
+    routes: &'a Routes,
+    reclaim: bool,
+}
+
+impl Deref for RoutesGuard<'_> {
+    type Target = Routes;
+
+    fn deref(&self) -> &Routes {
+        self.routes
+    }
+}
+
+impl Drop for RoutesGuard<'_> {
+    fn drop(&mut self) {
+        let last = self.shared.readers.fetch_sub(1, Ordering::SeqCst) == 1;
+        if last && self.reclaim && self.shared.pending.load(Ordering::SeqCst) {
+            // If another thread holds the lock, the routes are freed by the next thread to be
+            // done with them.
+            if let Some(mut retired) = self.shared.retired.try_lock() {
+                self.shared.reclaim(&mut retired);
+            }
+        }
+    }
+}
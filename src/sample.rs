@@ -0,0 +1,46 @@
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use log::Level;
+
+use crate::level::level_index;
+
+/// Keeps one in every so many records of each level, dropping the rest.
+#[derive(Debug)]
+pub(crate) struct Sampling {
+    /// How many records of each level there are for each one kept, indexed by [level_index].
+    rates: [u32; 5],
+    /// The number of records of each level seen so far, which decides the ones kept.
+    seen: [AtomicU32; 5],
+    /// The number of records dropped.
+    pub(crate) dropped: AtomicUsize,
+}
+
+impl Sampling {
+    pub(crate) fn new(rates: [u32; 5]) -> Sampling {
+        Self {
+            rates,
+            seen: Default::default(),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Whether to keep a record of the level, counting it as dropped if not.
+    ///
+    /// The first record of a level is always kept, and then every `rate`th one after it.
+    pub(crate) fn keep(&self, level: Level) -> bool {
+        let index = level_index(level);
+        let rate = self.rates[index];
+        if rate <= 1 {
+            return true;
+        }
+
+        if self.seen[index]
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(rate)
+        {
+            return true;
+        }
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+}
@@ -0,0 +1,194 @@
+#[cfg(feature = "std")]
+use alloc::ffi::CString;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::cell::{Cell, RefCell};
+use core::ffi::c_char;
+#[cfg(feature = "std")]
+use core::ffi::{c_void, CStr};
+use core::ptr;
+#[cfg(feature = "std")]
+use core::ptr::NonNull;
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::thread;
+
+#[cfg(feature = "std")]
+use crate::to_c_string;
+
+/// The id given to the next thread that logs. Zero is reserved for when it is unavailable.
+#[cfg(feature = "std")]
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[cfg(feature = "std")]
+thread_local! {
+    static CURRENT: Info = Info {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        name: RefCell::new(thread::current().name().map(to_c_string)),
+    };
+
+    /// Whether the current thread is inside [Log::log](log::Log::log).
+    static LOGGING: Cell<bool> = const { Cell::new(false) };
+
+    /// Whether the current thread is calling an error callback.
+    static REPORTING: Cell<bool> = const { Cell::new(false) };
+
+    /// The user data of the current thread for each logger it was set for, by the address of
+    /// the logger's dispatcher.
+    static DATA: RefCell<Vec<(usize, NonNull<c_void>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The identity of a thread, computed once the first time it logs.
+#[cfg(feature = "std")]
+struct Info {
+    id: u64,
+    /// Only replaced by [set_name] while the thread is not logging, so that the name is never
+    /// freed while a callback may be reading it.
+    name: RefCell<Option<CString>>,
+}
+
+/// The id and null-terminated name of the current thread.
+///
+/// The name is null if the thread is unnamed, and stays valid until the thread exits. Both are
+/// unavailable, and returned as zero and null, while the thread-locals of the thread are being
+/// destroyed.
+#[cfg(feature = "std")]
+pub(crate) fn current() -> (u64, *const c_char) {
+    CURRENT
+        .try_with(|info| {
+            let name = info
+                .name
+                .borrow()
+                .as_deref()
+                .map_or(ptr::null(), CStr::as_ptr);
+            (info.id, name)
+        })
+        .unwrap_or((0, ptr::null()))
+}
+
+/// Replace the name of the current thread passed to the callbacks, or go back to the name the
+/// standard library knows it by. Returns `false` if the thread is logging, or its thread-locals
+/// are being destroyed.
+#[cfg(feature = "std")]
+pub(crate) fn set_name(name: Option<&str>) -> bool {
+    if is_logging() {
+        return false;
+    }
+
+    CURRENT
+        .try_with(|info| {
+            let name = name.or(thread::current().name()).map(to_c_string);
+            *info.name.borrow_mut() = name;
+        })
+        .is_ok()
+}
+
+/// The user data of the current thread for the logger, if any was set.
+#[cfg(feature = "std")]
+pub(crate) fn data(logger: usize) -> Option<NonNull<c_void>> {
+    DATA.try_with(|data| {
+        let data = data.try_borrow().ok()?;
+        data.iter()
+            .find(|(key, _)| *key == logger)
+            .map(|&(_, data)| data)
+    })
+    .ok()
+    .flatten()
+}
+
+/// Set the user data of the current thread for the logger, or remove it, returning the previous
+/// data. Nothing is changed while the thread-locals of the thread are being destroyed.
+#[cfg(feature = "std")]
+pub(crate) fn set_data(logger: usize, new: Option<NonNull<c_void>>) -> Option<NonNull<c_void>> {
+    DATA.try_with(|data| {
+        let mut data = data.borrow_mut();
+        let index = data.iter().position(|(key, _)| *key == logger);
+        match (index, new) {
+            (Some(index), Some(new)) => Some(core::mem::replace(&mut data[index].1, new)),
+            (Some(index), None) => Some(data.remove(index).1),
+            (None, Some(new)) => {
+                data.push((logger, new));
+                None
+            }
+            (None, None) => None,
+        }
+    })
+    .ok()
+    .flatten()
+}
+
+/// Threads cannot be identified without the standard library.
+#[cfg(not(feature = "std"))]
+pub(crate) fn current() -> (u64, *const c_char) {
+    (0, ptr::null())
+}
+
+/// Marks the current thread as logging until dropped. See [enter].
+pub(crate) struct Entered(());
+
+/// Mark the current thread as logging, unless it already is.
+///
+/// This returns `None` when a callback, or anything it calls, logs again on the same thread,
+/// which could otherwise recurse without end or deadlock.
+#[cfg(feature = "std")]
+pub(crate) fn enter() -> Option<Entered> {
+    // While the thread-locals are being destroyed reentrancy cannot be detected, so the
+    // message is let through.
+    match LOGGING.try_with(|logging| logging.replace(true)) {
+        Ok(true) => None,
+        Ok(false) => Some(Entered(())),
+        Err(_) => Some(Entered(())),
+    }
+}
+
+/// Whether the current thread is inside [Log::log](log::Log::log).
+#[cfg(feature = "std")]
+pub(crate) fn is_logging() -> bool {
+    LOGGING.try_with(Cell::get).unwrap_or(false)
+}
+
+/// Reentrancy cannot be detected without thread-locals.
+#[cfg(not(feature = "std"))]
+pub(crate) fn enter() -> Option<Entered> {
+    Some(Entered(()))
+}
+
+#[cfg(feature = "std")]
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let _ = LOGGING.try_with(|logging| logging.set(false));
+    }
+}
+
+/// Marks the current thread as calling an error callback until dropped. See [report].
+pub(crate) struct Reporting(());
+
+/// Mark the current thread as calling an error callback, unless it already is.
+///
+/// This returns `None` when the error callback, or anything it calls, drops a message again on
+/// the same thread, so that reporting it does not recurse. Other threads still report theirs.
+#[cfg(feature = "std")]
+pub(crate) fn report() -> Option<Reporting> {
+    match REPORTING.try_with(|reporting| reporting.replace(true)) {
+        Ok(true) => None,
+        Ok(false) => Some(Reporting(())),
+        // Without its thread-locals the thread cannot tell if it is already reporting.
+        Err(_) => None,
+    }
+}
+
+/// Reentrancy cannot be detected without thread-locals, but nothing that drops messages is
+/// available without the standard library either.
+#[cfg(not(feature = "std"))]
+pub(crate) fn report() -> Option<Reporting> {
+    Some(Reporting(()))
+}
+
+#[cfg(feature = "std")]
+impl Drop for Reporting {
+    fn drop(&mut self) {
+        let _ = REPORTING.try_with(|reporting| reporting.set(false));
+    }
+}
@@ -0,0 +1,392 @@
+use alloc::ffi::CString;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ffi::{c_char, CStr};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::io;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::{Duration, Instant};
+
+use log::Level;
+
+use crate::buffer::Message;
+use crate::dispatch::{Dispatcher, Header, RecordStrings};
+use crate::sync::{Mutex, RwLock};
+use crate::{clock, thread as logger_thread};
+
+/// What an asynchronous logger does with a record when its queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait for the worker thread to make room, so that no records are lost.
+    Block,
+    /// Drop the record, counting it in
+    /// [LogHandle::dropped_count](crate::LogHandle::dropped_count).
+    ///
+    /// The worker thread also reports how many records were dropped with a warning of its own,
+    /// `N log messages dropped due to backpressure`, which is delivered at most once a second.
+    ///
+    /// ```
+    /// use std::ffi::{c_char, c_void, CStr};
+    /// use std::ptr::NonNull;
+    /// use std::sync::Mutex;
+    /// use std::time::Duration;
+    ///
+    /// use ffi_logger::{FfiCallback, FfiLogger, LogHandle, Overflow};
+    ///
+    /// static DELIVERED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    ///
+    /// extern "C" fn slow(_: Option<NonNull<c_void>>, message: *const c_char) -> isize {
+    ///     std::thread::sleep(Duration::from_millis(20));
+    ///     let message = unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned();
+    ///     DELIVERED.lock().unwrap().push(message);
+    ///     0
+    /// }
+    ///
+    /// let logger = unsafe {
+    ///     FfiLogger::builder(FfiCallback::Message(slow))
+    ///         .asynchronous(1, Overflow::Drop)
+    ///         .build()
+    /// };
+    /// let logger: &'static FfiLogger = Box::leak(Box::new(logger));
+    /// log::set_logger(logger).unwrap();
+    /// log::set_max_level(log::LevelFilter::Info);
+    ///
+    /// for i in 0..10 {
+    ///     log::info!("burst {i}");
+    /// }
+    /// let handle = LogHandle::new(logger);
+    /// handle.deinit();
+    ///
+    /// // Every dropped record is in a report, whether there was one or more.
+    /// let reported: usize = DELIVERED
+    ///     .lock()
+    ///     .unwrap()
+    ///     .iter()
+    ///     .filter_map(|message| message.strip_suffix(" log messages dropped due to backpressure"))
+    ///     .map(|dropped| dropped.parse::<usize>().unwrap())
+    ///     .sum();
+    /// assert!(reported > 0);
+    /// assert_eq!(reported, handle.dropped_count());
+    /// ```
+    Drop,
+}
+
+/// A formatted record waiting to be delivered.
+#[derive(Debug)]
+pub(crate) struct Queued {
+    header: Header,
+    /// The name of the thread that logged the record, as it may exit before the record is
+    /// delivered.
+    thread_name: Option<CString>,
+    strings: RecordStrings,
+    /// The formatted message, followed by a null terminator.
+    message: Vec<u8>,
+}
+
+// Safety: The thread name in the header is only used once it points into the record itself.
+unsafe impl Send for Queued {}
+
+impl Queued {
+    pub(crate) fn new(header: Header, strings: RecordStrings, message: Vec<u8>) -> Queued {
+        let thread_name = (!header.thread_name.is_null())
+            // Safety: The name of the current thread is valid until it exits.
+            .then(|| unsafe { CStr::from_ptr(header.thread_name) }.to_owned());
+
+        Self {
+            header,
+            thread_name,
+            strings,
+            message,
+        }
+    }
+
+    pub(crate) fn deliver(self, dispatcher: &Dispatcher) {
+        let Self {
+            mut header,
+            thread_name,
+            strings,
+            message,
+        } = self;
+        header.thread_name = thread_name.as_deref().map_or(ptr::null(), CStr::as_ptr);
+
+        dispatcher.dispatch(&header, || strings, &mut Message::new(&message));
+    }
+}
+
+enum Job {
+    Deliver(Queued),
+    /// Signals the sender once every record queued before it was delivered.
+    Flush(SyncSender<()>),
+}
+
+/// A thread delivering the records of an asynchronous logger from a bounded queue.
+#[derive(Debug)]
+pub(crate) struct Worker {
+    dispatcher: Arc<Dispatcher>,
+    /// The sending side of the queue, which is taken when stopping.
+    sender: RwLock<Option<SyncSender<Job>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    thread_id: ThreadId,
+    overflow: Overflow,
+    /// The number of records dropped because the queue was full, since they were last reported.
+    overflowed: Arc<AtomicUsize>,
+    /// How long [LogHandle::deinit](crate::LogHandle::deinit) waits for the worker thread.
+    pub(crate) deinit_timeout: Duration,
+}
+
+impl Worker {
+    pub(crate) fn spawn(
+        dispatcher: Arc<Dispatcher>,
+        capacity: usize,
+        overflow: Overflow,
+        deinit_timeout: Duration,
+        component: Option<&'static CStr>,
+    ) -> io::Result<Worker> {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let overflowed = Arc::new(AtomicUsize::new(0));
+        let thread = {
+            let dispatcher = dispatcher.clone();
+            let overflowed = overflowed.clone();
+            thread::Builder::new()
+                .name("ffi-logger".into())
+                .spawn(move || {
+                    let reporter = Reporter {
+                        dispatcher: &dispatcher,
+                        overflowed: &overflowed,
+                        component: component.map_or(ptr::null(), CStr::as_ptr),
+                        last: None,
+                    };
+                    run(&dispatcher, receiver, reporter);
+                })?
+        };
+
+        Ok(Self {
+            dispatcher,
+            sender: RwLock::new(Some(sender)),
+            thread_id: thread.thread().id(),
+            thread: Mutex::new(Some(thread)),
+            overflow,
+            overflowed,
+            deinit_timeout,
+        })
+    }
+
+    /// Queue the record for the worker thread, handing it back if the worker was stopped.
+    pub(crate) fn push(&self, queued: Queued) -> Option<Queued> {
+        // The sender is cloned out of the lock, so that stopping never waits on a record that
+        // is blocked on a full queue.
+        let Some(sender) = self.sender.read().clone() else {
+            return Some(queued);
+        };
+
+        // The worker thread would wait on itself if it blocked on its own queue.
+        let result = if self.overflow == Overflow::Block && !self.is_current() {
+            sender
+                .send(Job::Deliver(queued))
+                .map_err(|error| TrySendError::Disconnected(error.0))
+        } else {
+            sender.try_send(Job::Deliver(queued))
+        };
+
+        match result {
+            Ok(()) => None,
+            Err(TrySendError::Full(job)) => {
+                if let Job::Deliver(queued) = job {
+                    self.overflowed.fetch_add(1, Ordering::Relaxed);
+                    self.dispatcher
+                        .drop_message(queued.header.level, c"dropped because the queue was full");
+                }
+                None
+            }
+            Err(TrySendError::Disconnected(Job::Deliver(queued))) => Some(queued),
+            Err(TrySendError::Disconnected(Job::Flush(_))) => None,
+        }
+    }
+
+    /// Wait for every record queued so far to be delivered, for at most the timeout if there is
+    /// one. Returns `false` if that timed out.
+    ///
+    /// This returns right away on the worker thread itself, which cannot wait for itself.
+    pub(crate) fn flush(&self, timeout: Option<Duration>) -> bool {
+        let Some(sender) = self.sender.read().clone() else {
+            return true;
+        };
+        if self.is_current() {
+            return true;
+        }
+
+        let deadline = deadline(timeout);
+        let (done, finished) = mpsc::sync_channel(1);
+        let Some(deadline) = deadline else {
+            if sender.send(Job::Flush(done)).is_ok() {
+                let _ = finished.recv();
+            }
+            return true;
+        };
+
+        // The queue may be full, so waiting for room in it has to time out as well.
+        let mut job = Job::Flush(done);
+        loop {
+            match sender.try_send(job) {
+                Ok(()) => break,
+                Err(TrySendError::Full(full)) if Instant::now() < deadline => {
+                    job = full;
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(TrySendError::Full(_)) => return false,
+                Err(TrySendError::Disconnected(_)) => return true,
+            }
+        }
+        match finished.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(()) => true,
+            Err(mpsc::RecvTimeoutError::Timeout) => false,
+            Err(mpsc::RecvTimeoutError::Disconnected) => true,
+        }
+    }
+
+    /// Deliver every queued record and stop the worker thread, after which records are no
+    /// longer queued.
+    ///
+    /// If there is a timeout and the worker thread does not finish within it, it is left to
+    /// finish on its own and `false` is returned.
+    pub(crate) fn stop(&self, timeout: Option<Duration>) -> bool {
+        drop(self.sender.write().take());
+
+        let Some(thread) = self.thread.lock().take() else {
+            return true;
+        };
+        if self.is_current() {
+            return true;
+        }
+        if let Some(deadline) = deadline(timeout) {
+            while !thread.is_finished() {
+                if Instant::now() >= deadline {
+                    return false;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        let _ = thread.join();
+        true
+    }
+
+    fn is_current(&self) -> bool {
+        thread::current().id() == self.thread_id
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop(None);
+    }
+}
+
+/// How often a worker thread that is being waited for with a timeout is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// When a timeout starting now ends, or [None] if there is no timeout or it is too long to
+/// ever end.
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.and_then(|timeout| Instant::now().checked_add(timeout))
+}
+
+/// The shortest time between reports of the records dropped because the queue was full.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reports the records dropped because the queue was full through the callbacks, at most once
+/// every [REPORT_INTERVAL] so that the reports cannot flood the sink themselves.
+struct Reporter<'a> {
+    dispatcher: &'a Dispatcher,
+    overflowed: &'a AtomicUsize,
+    /// The null-terminated component of the logger, or null if it has none.
+    component: *const c_char,
+    /// When the last report was made.
+    last: Option<Instant>,
+}
+
+impl Reporter<'_> {
+    /// How long to wait before the dropped records can be reported, or [None] if there are none
+    /// to report.
+    fn wait(&self) -> Option<Duration> {
+        if self.overflowed.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let wait = self.last.map_or(Duration::ZERO, |last| {
+            REPORT_INTERVAL.saturating_sub(last.elapsed())
+        });
+        Some(wait)
+    }
+
+    /// Report the records dropped since the last report, if there were any. Unless `force` is
+    /// set, nothing is reported until [REPORT_INTERVAL] has passed since the last report.
+    fn report(&mut self, force: bool) {
+        if !force
+            && self
+                .last
+                .is_some_and(|last| last.elapsed() < REPORT_INTERVAL)
+        {
+            return;
+        }
+        let dropped = self.overflowed.swap(0, Ordering::Relaxed);
+        if dropped == 0 || self.dispatcher.is_unavailable(Level::Warn) {
+            return;
+        }
+        self.last = Some(Instant::now());
+
+        let (thread_id, thread_name) = logger_thread::current();
+        let header = Header {
+            level: Level::Warn,
+            line: 0,
+            timestamp: clock::unix_nanos(),
+            sequence: 0,
+            thread_id,
+            thread_name,
+            kv_count: 0,
+            component: self.component,
+            thread_data: None,
+            process_id: self.dispatcher.process_id,
+        };
+        let strings = || RecordStrings {
+            target: c"ffi_logger".into(),
+            module_path: None,
+            file: None,
+        };
+        let message = format!("{dropped} log messages dropped due to backpressure\0");
+        self.dispatcher
+            .dispatch(&header, strings, &mut Message::new(message.as_bytes()));
+    }
+}
+
+fn run(dispatcher: &Dispatcher, receiver: Receiver<Job>, mut reporter: Reporter) {
+    // This ends once the sender is dropped and every queued job was taken.
+    loop {
+        let job = match reporter.wait() {
+            Some(wait) => match receiver.recv_timeout(wait) {
+                Ok(job) => Some(job),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            },
+            None => match receiver.recv() {
+                Ok(job) => Some(job),
+                Err(_) => break,
+            },
+        };
+        // Dropped records are reported before the records queued after them.
+        reporter.report(false);
+
+        match job {
+            Some(Job::Deliver(queued)) if !dispatcher.is_unavailable(queued.header.level) => {
+                queued.deliver(dispatcher);
+            }
+            Some(Job::Flush(done)) => {
+                let _ = done.send(());
+            }
+            Some(Job::Deliver(_)) | None => {}
+        }
+    }
+    // The records dropped last are reported when stopping, even if a report was just made.
+    reporter.report(true);
+}